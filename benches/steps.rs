@@ -0,0 +1,58 @@
+// Steps per second for every solver on the example patterns, run with
+// cargo bench --no-default-features --bench steps
+// and add --features parallel to see what the extra cores buy
+
+use std::time::{Duration, Instant};
+
+use cloth_simulator::cloth::{Cloth, Solver};
+use cloth_simulator::drafting::{self, Draft};
+use cloth_simulator::progress::CancelToken;
+use cloth_simulator::project::Settings;
+
+// pattern and the detail in centimeters it gets cut at
+const PATTERNS: [(&str, f64); 2] = [("files/TopHat.svg", 1.0), ("files/Weaverling.svg", 2.0)];
+const SOLVERS: [Solver; 3] = [Solver::Springs, Solver::Xpbd, Solver::Implicit];
+// steps run before timing so the cloth is already moving
+const WARMUP_STEPS: u32 = 5;
+// keeps stepping until at least this long has gone by
+const MEASURE_TIME: Duration = Duration::from_secs(3);
+
+fn main() {
+    let settings = Settings::default();
+    for (file, detail) in PATTERNS {
+        let draft = Draft::new(file.to_string(), 0, 0, drafting::DEFAULT_TOLERANCE)
+            .unwrap_or_else(|err| panic!("could not read {}: {}", file, err));
+        for solver in SOLVERS {
+            let generate = Settings {
+                detail,
+                ..settings.clone()
+            }
+            .generate_settings(&draft);
+            let mut cloth =
+                Cloth::generate_from_draft(&draft, &generate, &mut |_| {}, &CancelToken::new())
+                    .unwrap();
+            cloth.timestep = settings.timestep as f32;
+            cloth.substeps = settings.substeps;
+            cloth.solver = solver;
+            cloth.compliance = settings.compliance as f32;
+            cloth.seam_compliance = settings.seam_compliance as f32;
+            for _ in 0..WARMUP_STEPS {
+                cloth.step();
+            }
+
+            let start = Instant::now();
+            let mut steps = 0;
+            while start.elapsed() < MEASURE_TIME {
+                cloth.step();
+                steps += 1;
+            }
+            println!(
+                "{:<22} {:>7} segments  {:<9} {:>8.2} steps/s",
+                file,
+                cloth.segment_count(),
+                format!("{:?}", solver),
+                steps as f64 / start.elapsed().as_secs_f64()
+            );
+        }
+    }
+}
@@ -0,0 +1,369 @@
+// Runs simulations without a display, meant for build servers and batch jobs
+
+use std::env;
+use std::io::{self, Write};
+use std::num::NonZeroU32;
+use std::path::Path;
+use std::process::ExitCode;
+
+use cloth_simulator::cloth::{Cloth, Solver, Update};
+use cloth_simulator::collider::{self, Collider, Shape};
+use cloth_simulator::drafting::Draft;
+use cloth_simulator::export::{self, MeshFormat, Recording};
+use cloth_simulator::material::{self, Material, Preset};
+use cloth_simulator::math::Vector3;
+use cloth_simulator::progress::CancelToken;
+use cloth_simulator::project::{self, Project, Settings};
+
+const USAGE: &str = "usage: weaverling simulate <pattern.svg | project.weaverling> [options]
+
+options:
+    --detail <cm>          grid spacing in centimeters, lower is finer
+    --tolerance <mm>       how far curves in an svg may stray once cut into lines
+    --presets <file>       adds the materials in a json file, give it before using them
+    --material <name>      fills in the fabric settings from a preset
+    --piece-material <n>=<name>
+                           makes piece n out of a preset instead, pieces count from 0
+                           left to right
+    --stiffness <n>        how many neighbors each segment holds on to
+    --gravity <m/s^2>
+    --damping <n>          how much speed is lost each second
+    --strength <n>         along the grainline, also sets the two below
+    --weft-strength <n>    across the grainline
+    --shear-strength <n>   on the bias
+    --seam-strength <n>    defaults to strength * 25
+    --bending <n>          how hard the fabric resists folding
+    --density <kg/m^2>
+    --friction <n>
+    --timestep <seconds>   how far each step moves the simulation
+    --substeps <n>         how many pieces each step is split into
+    --solver <name>        springs, xpbd or implicit
+    --update <name>        jacobi or gauss-seidel, how xpbd applies its links
+    --compliance <n>       how much xpbd links give, 0 is not at all
+    --seam-compliance <n>
+    --self-collision       keeps the cloth from passing through itself
+    --thickness <mm>       how far apart touching layers stay, pieces can have their own
+    --ground <m>           adds a floor at this height, the cloth starts 1 meter up
+    --sphere <x,y,z,radius>
+    --capsule <x,y,z,x,y,z,radius>
+                           from one end to the other, like a cylinder with round ends
+    --box <x,y,z,width,height,depth>
+                           centered on x,y,z and lined up with the axes
+    --collider-friction <n>
+                           for the colliders given after it (default 0.5)
+    --steps <n>            most steps to run (default 1000)
+    --settle <m/s>         stop early once the cloth has sped up past this
+                           and slowed back under it
+    --out <file>           where the mesh is written, .obj or .ply (default out.obj)
+                           or .glb for an animation of the whole run
+    --record-every <n>     steps between animation frames for .glb (default 10)
+
+settings not given come from the project file, or the gui defaults for svgs";
+
+struct Options {
+    input: String,
+    settings: Settings,
+    steps: u32,
+    settle: Option<f32>,
+    out: String,
+    record_every: NonZeroU32,
+    piece_materials: Vec<(usize, Material)>,
+}
+
+fn find_material(presets: &[Preset], name: &str) -> Result<Material, String> {
+    material::find(presets, name)
+        .copied()
+        .ok_or(format!("there is no material called \"{}\"", name))
+}
+
+fn is_glb(out: &str) -> bool {
+    Path::new(out)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("glb"))
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or(format!("{} needs a value", flag))?;
+    value
+        .parse::<T>()
+        .map_err(|_| format!("{} got \"{}\" which isnt a valid number", flag, value))
+}
+
+// A comma separated list of exactly count numbers
+fn parse_numbers<const N: usize>(flag: &str, value: Option<String>) -> Result<[f32; N], String> {
+    let value = value.ok_or(format!("{} needs a value", flag))?;
+    let numbers = value
+        .split(',')
+        .map(|number| parse_value(flag, Some(number.trim().to_string())))
+        .collect::<Result<Vec<f32>, String>>()?;
+    numbers
+        .try_into()
+        .map_err(|_| format!("{} needs {} numbers split by commas", flag, N))
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<(Options, Draft), String> {
+    match args.next().as_deref() {
+        Some("simulate") => {}
+        Some(command) => return Err(format!("unknown command \"{}\"", command)),
+        None => return Err("missing command".to_string()),
+    }
+    let input = args.next().ok_or("missing pattern file".to_string())?;
+
+    // projects bring their own settings, flags given after override them. svgs are only read
+    // once the flags are in since --tolerance changes how they come out
+    let (draft, mut settings) = if Path::new(&input)
+        .extension()
+        .is_some_and(|extension| extension == project::EXTENSION)
+    {
+        let project = Project::load(input.clone()).map_err(|err| err.to_string())?;
+        (Some(project.draft(0, 0)), project.settings)
+    } else {
+        (None, Settings::default())
+    };
+
+    let mut options = Options {
+        input,
+        // filled in once every flag is read
+        settings: Settings::default(),
+        steps: 1000,
+        settle: None,
+        out: "out.obj".to_string(),
+        record_every: NonZeroU32::new(10).unwrap(),
+        piece_materials: vec![],
+    };
+    let mut presets = material::presets();
+    let mut seam_strength: Option<f64> = None;
+    let mut weft_strength: Option<f64> = None;
+    let mut shear_strength: Option<f64> = None;
+    let mut collider_friction = collider::DEFAULT_FRICTION;
+    while let Some(flag) = args.next() {
+        match flag.as_str() {
+            "--detail" => settings.detail = parse_value(&flag, args.next())?,
+            "--tolerance" => settings.tolerance = parse_value(&flag, args.next())?,
+            "--presets" => {
+                let file = args.next().ok_or("--presets needs a value".to_string())?;
+                presets.extend(material::load_presets(file).map_err(|err| err.to_string())?);
+            }
+            "--material" => {
+                let name = args.next().ok_or("--material needs a value".to_string())?;
+                settings.set_material(&find_material(&presets, &name)?);
+            }
+            "--piece-material" => {
+                let value = args
+                    .next()
+                    .ok_or("--piece-material needs a value".to_string())?;
+                let (piece, name) = value
+                    .split_once('=')
+                    .ok_or("--piece-material has to look like 0=cotton".to_string())?;
+                let piece = parse_value(&flag, Some(piece.to_string()))?;
+                options
+                    .piece_materials
+                    .push((piece, find_material(&presets, name)?));
+            }
+            "--stiffness" => settings.stiffness = parse_value(&flag, args.next())?,
+            "--gravity" => settings.gravity = parse_value(&flag, args.next())?,
+            "--damping" => settings.damping = parse_value(&flag, args.next())?,
+            "--strength" => {
+                settings.strength = parse_value(&flag, args.next())?;
+                settings.weft_strength = settings.strength;
+                settings.shear_strength = settings.strength;
+                settings.seam_strength = settings.strength * 25.0;
+            }
+            "--weft-strength" => weft_strength = Some(parse_value(&flag, args.next())?),
+            "--shear-strength" => shear_strength = Some(parse_value(&flag, args.next())?),
+            "--seam-strength" => seam_strength = Some(parse_value(&flag, args.next())?),
+            "--bending" => settings.bending = parse_value(&flag, args.next())?,
+            "--density" => settings.density = parse_value(&flag, args.next())?,
+            "--friction" => settings.friction = parse_value(&flag, args.next())?,
+            "--timestep" => settings.timestep = parse_value(&flag, args.next())?,
+            "--substeps" => settings.substeps = parse_value(&flag, args.next())?,
+            "--solver" => {
+                settings.solver = match args.next().as_deref() {
+                    Some("springs") => Solver::Springs,
+                    Some("xpbd") => Solver::Xpbd,
+                    Some("implicit") => Solver::Implicit,
+                    _ => return Err("--solver has to be springs, xpbd or implicit".to_string()),
+                }
+            }
+            "--update" => {
+                settings.update = match args.next().as_deref() {
+                    Some("jacobi") => Update::Jacobi,
+                    Some("gauss-seidel") => Update::GaussSeidel,
+                    _ => return Err("--update has to be jacobi or gauss-seidel".to_string()),
+                }
+            }
+            "--compliance" => settings.compliance = parse_value(&flag, args.next())?,
+            "--seam-compliance" => settings.seam_compliance = parse_value(&flag, args.next())?,
+            "--self-collision" => settings.self_collision = true,
+            "--thickness" => settings.thickness = parse_value(&flag, args.next())?,
+            "--ground" | "--sphere" | "--capsule" | "--box" => {
+                let shape = match flag.as_str() {
+                    "--ground" => Shape::Ground {
+                        height: parse_value(&flag, args.next())?,
+                    },
+                    "--sphere" => {
+                        let [x, y, z, radius] = parse_numbers(&flag, args.next())?;
+                        let center = Vector3::new(x, y, z);
+                        Shape::Sphere { center, radius }
+                    }
+                    "--capsule" => {
+                        let [x, y, z, end_x, end_y, end_z, radius] =
+                            parse_numbers(&flag, args.next())?;
+                        let start = Vector3::new(x, y, z);
+                        let end = Vector3::new(end_x, end_y, end_z);
+                        Shape::Capsule { start, end, radius }
+                    }
+                    _ => {
+                        let [x, y, z, width, height, depth] = parse_numbers(&flag, args.next())?;
+                        let center = Vector3::new(x, y, z);
+                        let size = Vector3::new(width, height, depth);
+                        Shape::Box { center, size }
+                    }
+                };
+                settings.colliders.push(Collider {
+                    shape,
+                    friction: collider_friction,
+                });
+            }
+            "--collider-friction" => collider_friction = parse_value(&flag, args.next())?,
+            "--steps" => options.steps = parse_value(&flag, args.next())?,
+            "--settle" => options.settle = Some(parse_value(&flag, args.next())?),
+            "--out" => options.out = args.next().ok_or("--out needs a value".to_string())?,
+            "--record-every" => {
+                options.record_every = NonZeroU32::new(parse_value(&flag, args.next())?)
+                    .ok_or("--record-every has to be above 0".to_string())?
+            }
+            _ => return Err(format!("unknown option \"{}\"", flag)),
+        }
+    }
+    if let Some(seam_strength) = seam_strength {
+        settings.seam_strength = seam_strength;
+    }
+    if let Some(weft_strength) = weft_strength {
+        settings.weft_strength = weft_strength;
+    }
+    if let Some(shear_strength) = shear_strength {
+        settings.shear_strength = shear_strength;
+    }
+    if MeshFormat::from_path(Path::new(&options.out)).is_none() && !is_glb(&options.out) {
+        return Err(format!(
+            "cant tell what format \"{}\" should be, use .obj, .ply or .glb",
+            options.out
+        ));
+    }
+    if settings.timestep <= 0.0 {
+        return Err("--timestep has to be above 0".to_string());
+    }
+    if settings.detail <= 0.0 {
+        return Err("--detail has to be above 0".to_string());
+    }
+    if settings.density <= 0.0 {
+        return Err("--density has to be above 0".to_string());
+    }
+    if settings.thickness <= 0.0 {
+        return Err("--thickness has to be above 0".to_string());
+    }
+    if settings.tolerance <= 0.0 {
+        return Err("--tolerance has to be above 0".to_string());
+    }
+    if settings.update == Update::GaussSeidel && settings.solver != Solver::Xpbd {
+        return Err("--update gauss-seidel only works with --solver xpbd".to_string());
+    }
+    let draft = match draft {
+        Some(draft) => draft,
+        None => Draft::new(options.input.clone(), 0, 0, settings.tolerance as f32)
+            .map_err(|err| err.to_string())?,
+    };
+    options.settings = settings;
+    Ok((options, draft))
+}
+
+fn simulate(options: &Options, draft: &Draft) -> Result<(), String> {
+    let settings = &options.settings;
+    let mut stderr = io::stderr();
+    let cloth = Cloth::generate_from_draft(
+        draft,
+        &settings.generate_settings(draft),
+        &mut |fraction: f64| {
+            let _ = write!(stderr, "\rrasterizing {:>3.0}%", fraction * 100.0);
+        },
+        &CancelToken::new(),
+    );
+    eprintln!();
+    let mut cloth = cloth.ok_or("cloth generation was cancelled".to_string())?;
+    cloth.timestep = settings.timestep as f32;
+    cloth.substeps = settings.substeps;
+    cloth.solver = settings.solver;
+    cloth.update = settings.update;
+    cloth.compliance = settings.compliance as f32;
+    cloth.seam_compliance = settings.seam_compliance as f32;
+    cloth.self_collision = settings.self_collision;
+    cloth.thickness = settings.thickness as f32 / 1000.0;
+    cloth.colliders = settings.colliders.clone();
+    for (piece, material) in &options.piece_materials {
+        if *piece >= cloth.section_count() {
+            return Err(format!(
+                "there is no piece {}, the pattern has {}",
+                piece,
+                cloth.section_count()
+            ));
+        }
+        cloth.set_section_material(*piece, Some(*material));
+    }
+
+    let mut recording = is_glb(&options.out).then(|| Recording::new(&cloth, options.record_every));
+    let mut steps_run = 0;
+    // everything starts out still, so only count it as settled once it got going and slowed
+    // back down
+    let mut moving = false;
+    let mut settled = false;
+    while steps_run < options.steps {
+        cloth.step();
+        if let Some(recording) = &mut recording {
+            recording.after_step(&cloth);
+        }
+        steps_run += 1;
+        if let Some(settle) = options.settle {
+            let speed = cloth.max_speed();
+            if speed >= settle {
+                moving = true;
+            } else if moving {
+                settled = true;
+                break;
+            }
+        }
+    }
+    eprintln!(
+        "{}: {} {} steps on {} segments",
+        options.input,
+        if settled { "settled after" } else { "ran" },
+        steps_run,
+        cloth.segment_count()
+    );
+
+    let saved = match recording {
+        Some(recording) => {
+            eprintln!("writing {} frames", recording.frame_count());
+            recording.save_glb(Path::new(&options.out))
+        }
+        None => export::save_mesh(&cloth, Path::new(&options.out)),
+    };
+    saved.map_err(|err| format!("could not write {}: {}", options.out, err))
+}
+
+fn main() -> ExitCode {
+    let (options, draft) = match parse_args(env::args().skip(1)) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+    match simulate(&options, &draft) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
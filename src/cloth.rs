@@ -1,12 +1,39 @@
-use async_channel::{Receiver, Sender};
-use raylib::prelude::*;
-use std::ops::{Add, Sub};
-use std::{collections::HashMap, f32, usize};
+#[cfg(feature = "gui")]
+use raylib::prelude::{RaylibDraw3D, RaylibDrawHandle, RaylibMode3D, Vector3 as RVector3, color};
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::ops::{Add, Index, Sub};
 
-use crate::Message;
-use crate::drafting::Draft;
+use serde::{Deserialize, Serialize};
 
-#[derive(PartialEq, Eq, Clone, Copy)]
+use crate::collider::Collider;
+use crate::drafting::{DEFAULT_THICKNESS, Draft};
+use crate::material::{DEFAULT_DENSITY, Material};
+use crate::math::{Vector2, Vector3};
+use crate::parallel;
+use crate::progress::{CancelToken, Progress};
+use crate::spatial::SpatialHash;
+
+// what step uses unless told otherwise, the gui draws at 30 fps
+pub const DEFAULT_TIMESTEP: f32 = 1.0 / 30.0;
+// how hard rigid segments get pulled back onto their shared plane, per second squared
+const RIGID_STRENGTH: f32 = 270.0;
+
+// What generate_from_draft builds the cloth with besides the draft itself
+#[derive(Clone, Copy, Debug)]
+pub struct GenerateSettings {
+    // meters between neighboring segments
+    pub scale: f32,
+    // the same spacing in drawing units
+    pub detail: f32,
+    // how many cells out each segment gets tied to its neighbors
+    pub stiffness: u32,
+    pub gravity: f32,
+    pub material: Material,
+    pub seam_strength: f32,
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy)]
 pub struct Index3 {
     pub x: i32,
     pub y: i32,
@@ -91,74 +118,430 @@ impl Index3 {
     }
 }
 
-// A theoretical square patch of fabric
+// What rasterizing found at one grid cell, only kept while the cloth is being made
 #[derive(Clone, Copy)]
-pub struct ClothSegmentFrag {
+struct ClothSegmentFrag {
     index: Index3,
-    position: Vector3,
-    pub velocity: Vector3,
     pinned: bool,
     rigid: bool,
-    pub link_vector: Option<f32>,
-    pub link_number: Option<u32>,
-    pub line_id: usize,
-}
-pub struct ClothSegment {
-    frag: ClothSegmentFrag,
-    neighbors: Vec<Index3>,
-    neighbor_index: Vec<usize>,
-    second_neighbors: Vec<Option<Index3>>,
-    second_neighbor_index: Vec<Option<usize>>,
+    link_vector: Option<f32>,
+    link_number: Option<u32>,
+    line_id: usize,
+}
+// Which way a link runs compared to the threads of its piece. Woven cloth barely stretches along
+// the threads but gives a lot on the bias, so a link gets the warp strength moved towards the
+// weft and shear ones by these amounts. A link straight along the weft has weft 1, one at 45
+// degrees has shear 1
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Grain {
+    pub weft: f32,
+    pub shear: f32,
+}
+
+impl Grain {
+    pub fn strength(&self, warp: f32, weft: f32, shear: f32) -> f32 {
+        warp + (weft - warp) * self.weft + (shear - warp) * self.shear
+    }
+}
+
+// pattern pieces without a grainline are cut with the warp running down the page
+const DEFAULT_GRAIN: Vector2 = Vector2 { x: 0.0, y: 1.0 };
+
+// meters between the starting heights of neighboring layers
+const LAYER_GAP: f32 = 0.02;
+
+// How a segment is tied to one of its neighbors, worked out once when the cloth is made
+#[derive(Clone, Copy, PartialEq)]
+pub enum LinkKind {
+    // fabric in the same piece, pulls back to the grid spacing
+    Section(Grain),
+    // sewn to a linked line on another edge, pulls the two together
+    Seam,
+    // close on the grid but in another piece, doesnt pull at all
+    Unlinked,
+}
+
+#[derive(Clone, Copy)]
+pub struct Link {
     pub index: usize,
+    pub kind: LinkKind,
+    // resting distance in grid cells
+    pub rest: f32,
+}
+
+// A pair of segments the xpbd solver keeps at their resting distance, each pair is listed once
+#[derive(Clone, Copy)]
+struct Constraint {
+    a: usize,
+    b: usize,
+    kind: LinkKind,
+    rest: f32,
 }
 
+// Three segments in a straight row on the grid. Bending pushes the middle back in line with the
+// ends, which on a flat grid is the isometric bending model from
+// https://www.cs.columbia.edu/cg/pdfs/10_ds.pdf
+#[derive(Clone, Copy)]
+struct Bend {
+    ends: [usize; 2],
+    middle: usize,
+}
+
+// How step moves the cloth, picked when rendering
+#[derive(Clone, Copy, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub enum Solver {
+    // every link pushes on the velocity like a spring
+    #[default]
+    Springs,
+    // https://matthias-research.github.io/pages/publications/XPBD.pdf
+    // every link is a distance constraint fixed up on the positions, stays stable however stiff
+    Xpbd,
+    // https://www.cs.cmu.edu/~baraff/papers/sig98.pdf
+    // backward euler on the same springs, solved with conjugate gradient so big timesteps and
+    // very stiff fabric dont blow up
+    Implicit,
+}
+
+// How xpbd applies what every link asks for. Springs always works every force out from where
+// things were at the start of the substep, its explicit so moving some segments early can make
+// it blow up, and implicit solves everything at once
+#[derive(Clone, Copy, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub enum Update {
+    // every link is worked out from the same positions before any of them are applied, so the
+    // order segments were found in doesnt matter and mirrored pieces move the same
+    #[default]
+    Jacobi,
+    // links are applied a group at a time, no two in a group touching the same segment so a
+    // group comes out the same in any order. settles in fewer passes than jacobi
+    GaussSeidel,
+}
+
+// xpbd jacobi moves each segment by the average of what its links ask for, which undershoots,
+// so it gets pushed a bit further. https://mmacklin.com/2014-TOG-Macklin.pdf uses 1 to 2
+const JACOBI_RELAXATION: f32 = 1.5;
+
+// how hard each spring resists being moved, as a 3x3 matrix
+type Block = [[f32; 3]; 3];
+
+// one pass over the constraints leaves long pieces hanging off a pin stretched, and that stretch
+// snaps back as speed next substep until it blows up. a few passes keep it settled
+const XPBD_ITERATIONS: usize = 4;
+
+// conjugate gradient gives up after this many rounds even if it hasnt converged
+const CG_ITERATIONS: usize = 100;
+const CG_TOLERANCE: f32 = 1e-6;
+
+// Scratch space the implicit solver keeps between steps so it doesnt allocate each time
+#[derive(Default)]
+struct ImplicitBuffers {
+    // one per constraint, how hard it resists being moved and what it pulls with
+    springs: Vec<(Block, Vector3)>,
+    // one per constraint and bend, what each pushes on its ends during a round
+    pulls: Vec<Vector3>,
+    bend_pulls: Vec<Vector3>,
+    rhs: Vec<Vector3>,
+    delta: Vec<Vector3>,
+    residual: Vec<Vector3>,
+    direction: Vec<Vector3>,
+    product: Vec<Vector3>,
+}
+
+// segments of one piece this many grid cells apart or closer are already held apart by their
+// links and bends, so they never push off each other
+const COLLISION_CELLS: i32 = 2;
+// times contacts are found and pushed apart each substep, pushing one apart can make another
+const COLLISION_ITERATIONS: usize = 4;
+// how far outside a triangle as a share of it a point can be and still count as over it, so
+// points right on an edge or corner dont slip between the triangles either side
+const EDGE_SLACK: f32 = 0.01;
+// the same for where a segment started. the cloth slides about during a substep so it only has
+// to have started over this triangle or one next to it to have come from that side
+const SIDE_SLACK: f32 = 1.0;
+
+// One spot where the cloth is closer to itself than its thickness, a segment against a point on
+// a triangle. Against another segment all three corners are that segment and only the first
+// weight is used
+#[derive(Clone, Copy)]
+struct Contact {
+    segment: usize,
+    corners: [usize; 3],
+    // how much of the touching point each corner makes up
+    weights: [f32; 3],
+    // points from the triangle towards segment
+    normal: Vector3,
+    // how far apart along normal they should be
+    gap: f32,
+}
+
+// Scratch space for self collision and colliders, kept between steps like ImplicitBuffers
+#[derive(Default)]
+struct CollisionBuffers {
+    triangles: SpatialHash,
+    segments: SpatialHash,
+    // one list per segment of what it touches
+    contacts: Vec<Vec<Contact>>,
+    // how far the contacts or colliders have moved each segment, taken into its velocity at
+    // the end
+    pushes: Vec<Vector3>,
+}
+
+fn block_mul(block: &Block, v: Vector3) -> Vector3 {
+    Vector3 {
+        x: block[0][0] * v.x + block[0][1] * v.y + block[0][2] * v.z,
+        y: block[1][0] * v.x + block[1][1] * v.y + block[1][2] * v.z,
+        z: block[2][0] * v.x + block[2][1] * v.y + block[2][2] * v.z,
+    }
+}
+
+fn dot_all(a: &[Vector3], b: &[Vector3]) -> f32 {
+    parallel::sum(a.len(), |index| a[index].dot(b[index]))
+}
+
+// Moves a segment by the average of what the links touching it ask for, see JACOBI_RELAXATION
+fn relax(position: &mut Vector3, moves: impl Iterator<Item = Vector3>) {
+    let mut total = Vector3::zero();
+    let mut count = 0;
+    for correction in moves {
+        total += correction;
+        count += 1;
+    }
+    if count > 0 {
+        *position += total * (JACOBI_RELAXATION / count as f32);
+    }
+}
+
+// How much of point each corner of the triangle makes up, all of them are 0 or more when point
+// lies inside it
+fn barycentric(point: Vector3, corners: [Vector3; 3]) -> [f32; 3] {
+    let ab = corners[1] - corners[0];
+    let ac = corners[2] - corners[0];
+    let ap = point - corners[0];
+    let (d00, d01, d11) = (ab.dot(ab), ab.dot(ac), ac.dot(ac));
+    let (d20, d21) = (ap.dot(ab), ap.dot(ac));
+    let denominator = d00 * d11 - d01 * d01;
+    if denominator == 0.0 {
+        return [-1.0; 3];
+    }
+    let b = (d11 * d20 - d01 * d21) / denominator;
+    let c = (d00 * d21 - d01 * d20) / denominator;
+    [1.0 - b - c, b, c]
+}
+
+// Splits items into groups where no two items in a group touch the same segment, the first
+// group that fits wins. Not the fewest groups possible but close enough on a grid
+fn color_groups(items: impl Iterator<Item = Vec<usize>>, segments: usize) -> Vec<Vec<usize>> {
+    let mut groups: Vec<Vec<usize>> = vec![];
+    // which groups already touch each segment
+    let mut taken: Vec<Vec<usize>> = vec![vec![]; segments];
+    for (index, members) in items.enumerate() {
+        let mut color = 0;
+        while members.iter().any(|member| taken[*member].contains(&color)) {
+            color += 1;
+        }
+        if color == groups.len() {
+            groups.push(vec![]);
+        }
+        groups[color].push(index);
+        for member in members {
+            taken[member].push(color);
+        }
+    }
+    groups
+}
+
+// Lists of lists packed into one array, list i is items[offsets[i]..offsets[i + 1]]. Going
+// through every list reads straight along memory instead of jumping between small allocations
+struct Csr<T> {
+    offsets: Vec<usize>,
+    items: Vec<T>,
+}
+
+// no lists at all
+impl<T> Default for Csr<T> {
+    fn default() -> Self {
+        Csr {
+            offsets: vec![0],
+            items: vec![],
+        }
+    }
+}
+
+impl<T> FromIterator<Vec<T>> for Csr<T> {
+    fn from_iter<I: IntoIterator<Item = Vec<T>>>(lists: I) -> Self {
+        let mut csr = Csr::default();
+        for list in lists {
+            csr.items.extend(list);
+            csr.offsets.push(csr.items.len());
+        }
+        csr
+    }
+}
+
+impl<T> Index<usize> for Csr<T> {
+    type Output = [T];
+    fn index(&self, list: usize) -> &[T] {
+        &self.items[self.offsets[list]..self.offsets[list + 1]]
+    }
+}
+
+// Each segment is spread over the arrays up to links, segment i being entry i of every one. The
+// solvers only read the arrays they need so a step runs straight through memory
 pub struct Cloth {
-    pub segments: Vec<ClothSegment>,
+    // meters and meters per second
+    positions: Vec<Vector3>,
+    velocities: Vec<Vector3>,
+    // 0 for pinned segments, worked out at the start of every step along with materials
+    inverse_masses: Vec<f32>,
+    pinned: Vec<bool>,
+    rigid: Vec<bool>,
+    // on a linked line, only used to color the drawing
+    #[cfg(feature = "gui")]
+    seamed: Vec<bool>,
+    // where each segment sits on the grid
+    cells: Vec<Index3>,
+    // which entry of sections each segment is in, filled in by discover_section
+    segment_sections: Vec<Option<usize>>,
+    // every neighbor each segment is tied to, seams included
+    links: Csr<Link>,
     sections: Vec<Vec<usize>>,
+    // warp direction of each section on the flat pattern
+    grains: Vec<Vector2>,
+    // meters, one per section, None uses thickness
+    thicknesses: Vec<Option<f32>>,
+    // which segment sits at each grid cell, so neighbors are found without scanning
+    grid: HashMap<Index3, usize>,
     scale: f32,
 
+    // meters per second squared
     pub gravity: f32,
-    pub drag: f32,
-    pub strength: f32,
+    // what every piece is made of unless it was given its own. strengths are spring pull per
+    // meter of stretch per second squared on fabric of the default density, damping is how
+    // much of the velocity is lost each second
+    pub material: Material,
     pub seam_strength: f32,
     pub stiffness: u32,
+    // seconds one call to step moves the simulation forward
+    pub timestep: f32,
+    // how many times step splits up its timestep, more is steadier with stiff springs
+    pub substeps: u32,
+    pub solver: Solver,
+    pub update: Update,
+    // how far xpbd lets a link stretch under load, 0 is not at all
+    pub compliance: f32,
+    pub seam_compliance: f32,
+    // keeps the cloth from passing through itself, folded and layered pieces stay apart by
+    // their thickness
+    pub self_collision: bool,
+    // meters, how thick pieces without their own are
+    pub thickness: f32,
+    // the floor and whatever else the cloth lands on, segments stay half their thickness out
+    pub colliders: Vec<Collider>,
 
+    // one per section, None uses material
+    section_materials: Vec<Option<Material>>,
+    // what each segment is made of, worked out at the start of every step
+    materials: Vec<Material>,
     quads: Vec<Vec<u32>>,
+    // every quad as two triangles, leaving out ones that span two pieces
+    surface: Vec<[usize; 3]>,
+    // what each segment is sewn to
+    seam_partners: Csr<usize>,
+    constraints: Vec<Constraint>,
+    // how much each constraint has pushed so far this substep
+    lambdas: Vec<f32>,
+    bends: Vec<Bend>,
+    bend_lambdas: Vec<Vector3>,
+    // which constraints and bends each segment is part of, lowest first so adding up what
+    // they push always goes in the same order
+    segment_constraints: Csr<usize>,
+    segment_bends: Csr<usize>,
+    // groups for gauss seidel, see Update
+    constraint_colors: Vec<Vec<usize>>,
+    bend_colors: Vec<Vec<usize>>,
+    // the springs solver works these out for everything before moving anything, so the order
+    // segments were found in doesnt matter
+    accelerations: Vec<Vector3>,
+    // what each xpbd link asks for this pass, worked out for every link before any are applied
+    constraint_moves: Vec<Option<(f32, Vector3, Vector3)>>,
+    bend_moves: Vec<Option<(Vector3, [Vector3; 3])>>,
+    // positions from the start of the substep, kept around so step doesnt allocate
+    snapshot: Vec<Vector3>,
+    implicit: ImplicitBuffers,
+    collision: CollisionBuffers,
 }
 
 impl Cloth {
     pub fn generate_from_none() -> Self {
         Cloth {
-            segments: vec![],
+            positions: vec![],
+            velocities: vec![],
+            inverse_masses: vec![],
+            pinned: vec![],
+            rigid: vec![],
+            #[cfg(feature = "gui")]
+            seamed: vec![],
+            cells: vec![],
+            segment_sections: vec![],
+            links: Csr::default(),
             sections: vec![],
+            grains: vec![],
+            thicknesses: vec![],
+            grid: HashMap::new(),
             scale: 0.0,
             gravity: 0.0,
-            drag: 0.0,
-            strength: 0.0,
+            material: Material::default(),
             seam_strength: 0.0,
             stiffness: 0,
+            timestep: DEFAULT_TIMESTEP,
+            substeps: 1,
+            solver: Solver::Springs,
+            update: Update::Jacobi,
+            compliance: 0.0,
+            seam_compliance: 0.0,
+            self_collision: false,
+            thickness: DEFAULT_THICKNESS / 1000.0,
+            colliders: vec![],
+            section_materials: vec![],
+            materials: vec![],
             quads: vec![],
+            surface: vec![],
+            seam_partners: Csr::default(),
+            constraints: vec![],
+            lambdas: vec![],
+            bends: vec![],
+            bend_lambdas: vec![],
+            segment_constraints: Csr::default(),
+            segment_bends: Csr::default(),
+            constraint_colors: vec![],
+            bend_colors: vec![],
+            accelerations: vec![],
+            constraint_moves: vec![],
+            bend_moves: vec![],
+            snapshot: vec![],
+            implicit: ImplicitBuffers::default(),
+            collision: CollisionBuffers::default(),
         }
     }
     pub fn generate_from_draft(
         draft: &Draft,
-        scale: f32,
-        stiffness: u32,
-        detail: f32,
-        sender: &Sender<Message>,
-        receiver: &Receiver<Message>,
-        gravity: f32,
-        drag: f32,
-        strength: f32,
-        seam_strength: f32,
+        settings: &GenerateSettings,
+        progress: &mut dyn Progress,
+        cancel: &CancelToken,
     ) -> Option<Self> {
-        sender
-            .send_blocking(Message::RenderProgress(0.0))
-            .expect("The channel needs to be open.");
+        progress.report(0.0);
+        let GenerateSettings {
+            scale,
+            detail,
+            stiffness,
+            gravity,
+            material,
+            seam_strength,
+        } = *settings;
 
-        let mut segments: Vec<ClothSegment> = vec![];
+        let mut positions: Vec<Vector3> = vec![];
         let mut segment_links: HashMap<u32, Vec<u32>> = HashMap::new();
         let mut segment_frags: Vec<ClothSegmentFrag> = vec![];
+        let mut grid: HashMap<Index3, usize> = HashMap::new();
 
         let (min_bound, max_bound) = draft.get_bounds();
         // since rust doesnt have good for loops i have to do this ugly syntax
@@ -167,11 +550,7 @@ impl Cloth {
         let mut y_index_max = 0;
         let mut insert_index: u32 = 0;
         while x < max_bound.x {
-            sender
-                .send_blocking(Message::RenderProgress(
-                    (((x - min_bound.x) / (max_bound.x - min_bound.x)) / 3.0) as f64,
-                ))
-                .expect("The channel needs to be open.");
+            progress.report((((x - min_bound.x) / (max_bound.x - min_bound.x)) / 3.0) as f64);
             let mut check = Vector2 { x, y: min_bound.y };
             let mut y_step = 0;
 
@@ -208,7 +587,7 @@ impl Cloth {
 
                             // lower point should be p2 to avoid linking issues
                             if line.p1.x < line.p2.x
-                                || (line.p1.x == line.p2.x && line.p1.y < line.p1.y)
+                                || (line.p1.x == line.p2.x && line.p1.y < line.p2.y)
                             {
                                 true_p1 = line.p2;
                                 true_p2 = line.p1;
@@ -237,33 +616,21 @@ impl Cloth {
                             y: 0,
                             z: y_step,
                         },
-                        position: Vector3 {
-                            x: x_step.as_f32() * scale,
-                            y: 1.0,
-                            z: y_step.as_f32() * scale,
-                        },
-                        velocity: Vector3::zero(),
                         pinned,
                         rigid,
                         link_vector,
                         link_number,
                         line_id,
                     };
-                    segments.push(ClothSegment {
-                        frag,
-                        neighbors: vec![],
-                        neighbor_index: vec![],
-                        second_neighbors: vec![],
-                        second_neighbor_index: vec![],
-                        index: insert_index as usize,
+                    positions.push(Vector3 {
+                        x: x_step as f32 * scale,
+                        y: 1.0,
+                        z: y_step as f32 * scale,
                     });
                     segment_frags.push(frag);
-                    if link_number.is_some() {
-                        let number = link_number.unwrap();
-                        if !segment_links.contains_key(&number) {
-                            segment_links.insert(number, vec![]);
-                        }
-                        segment_links.get_mut(&number).unwrap().push(insert_index);
+                    grid.insert(frag.index, insert_index as usize);
+                    if let Some(number) = link_number {
+                        segment_links.entry(number).or_default().push(insert_index);
                     }
                     insert_index += 1;
                 }
@@ -278,126 +645,213 @@ impl Cloth {
         }
         let x_index_max = x_step;
 
+        let count = positions.len();
         let mut ret = Cloth {
-            segments,
-            sections: vec![],
+            positions,
+            velocities: vec![Vector3::zero(); count],
+            pinned: segment_frags.iter().map(|frag| frag.pinned).collect(),
+            rigid: segment_frags.iter().map(|frag| frag.rigid).collect(),
+            #[cfg(feature = "gui")]
+            seamed: segment_frags
+                .iter()
+                .map(|frag| frag.link_number.is_some())
+                .collect(),
+            cells: segment_frags.iter().map(|frag| frag.index).collect(),
+            segment_sections: vec![None; count],
+            grid,
             scale,
             gravity,
-            drag,
-            strength,
+            material,
             seam_strength,
             stiffness,
-            quads: vec![],
+            ..Cloth::generate_from_none()
         };
 
         let mut sections: Vec<Vec<usize>> = vec![];
         let mut total_so_far = 0;
-        'sectional: for segment in &ret.segments {
-            for section in &sections {
-                if section.contains(&segment.index) {
-                    continue 'sectional;
-                }
-            }
-            sections.push(vec![]);
-            let section_res = ret.discover_section(
-                segment.index,
-                sections,
-                sender,
-                receiver,
-                ret.segments.len(),
-                total_so_far,
-            );
-            if section_res.is_none() {
-                return None;
+        for index in 0..count {
+            if ret.segment_sections[index].is_some() {
+                continue;
             }
-            sections = section_res.unwrap();
-            total_so_far += sections.last().unwrap().len();
+            let section =
+                ret.discover_section(index, sections.len(), progress, cancel, total_so_far)?;
+            total_so_far += section.len();
+            sections.push(section);
         }
         ret.sections = sections;
+        ret.section_materials = vec![None; ret.sections.len()];
+
+        // a grainline belongs to whichever piece its middle lands in
+        ret.grains = vec![DEFAULT_GRAIN; ret.sections.len()];
+        for grainline in &draft.grainlines {
+            let Some(section) = ret.section_at(grainline.middle(), min_bound, detail) else {
+                continue;
+            };
+            ret.grains[section] = grainline.direction();
+        }
 
+        ret.thicknesses = vec![None; ret.sections.len()];
+        for piece in &draft.pieces {
+            let Some(section) = ret.section_at(piece.anchor, min_bound, detail) else {
+                continue;
+            };
+            ret.section_materials[section] = piece.material.as_ref().map(|preset| preset.material);
+            ret.thicknesses[section] = piece.thickness.map(|thickness| thickness / 1000.0);
+            if let Some(grain) = piece.grain() {
+                ret.grains[section] = grain;
+            }
+            for &index in &ret.sections[section] {
+                ret.positions[index].y += piece.layer as f32 * LAYER_GAP;
+            }
+        }
+
+        // only needed until the links, bends and quads have been found from them
+        let mut neighbors: Vec<Vec<usize>> = vec![vec![]; count];
+        let mut second_neighbors: Vec<Vec<usize>> = vec![vec![]; count];
         for x in 0..x_index_max {
-            let cancel_check = receiver.try_recv();
-            match cancel_check {
-                Ok(Message::Back) => {
-                    return None;
-                }
-                _ => {}
+            if cancel.is_cancelled() {
+                return None;
             }
-            sender
-                .send_blocking(Message::RenderProgress(
-                    ((x as f32 / x_index_max as f32) / 3.0 + 2.0 / 3.0) as f64,
-                ))
-                .expect("The channel needs to be open.");
+            progress.report(((x as f32 / x_index_max as f32) / 3.0 + 2.0 / 3.0) as f64);
             for y in 0..y_index_max {
-                match ret.find_index_info(Index3 { x, y: 0, z: y }) {
-                    None => {}
-                    Some((segment, neighbors, second_neighbors)) => {
-                        segment.neighbors = neighbors.0;
-                        segment.neighbor_index = neighbors.1;
-                        segment.second_neighbors = second_neighbors.0;
-                        segment.second_neighbor_index = second_neighbors.1;
-
-                        if segment.frag.link_vector.is_some() {
-                            let number = segment.frag.link_number.unwrap();
-                            let mut min_dist = f32::INFINITY;
-                            let mut segment_index: Option<usize> = None;
-                            for index in segment_links[&number].clone() {
-                                if index as usize == segment.index {
-                                    continue;
-                                }
-                                let dist = (segment_frags[index as usize].link_vector.unwrap()
-                                    - segment.frag.link_vector.unwrap())
-                                .abs();
-
-                                if dist < min_dist {
-                                    min_dist = dist;
-                                    segment_index = Some(index as usize);
-                                }
-                            }
-                            match segment_index {
-                                None => {}
-                                Some(index) => {
-                                    segment.neighbors.push(segment_frags[index as usize].index);
-                                    segment.neighbor_index.push(index);
-                                }
-                            }
+                let cell = Index3 { x, y: 0, z: y };
+                let Some(&index) = ret.grid.get(&cell) else {
+                    continue;
+                };
+                let (mut found, second) = ret.get_neighbors(cell);
+
+                let frag = segment_frags[index];
+                if let (Some(link_vector), Some(number)) = (frag.link_vector, frag.link_number) {
+                    let mut min_dist = f32::INFINITY;
+                    let mut segment_index: Option<usize> = None;
+                    for &other in &segment_links[&number] {
+                        let other = other as usize;
+                        if other == index {
+                            continue;
+                        }
+                        let dist = (segment_frags[other].link_vector.unwrap() - link_vector).abs();
+
+                        if dist < min_dist {
+                            min_dist = dist;
+                            segment_index = Some(other);
                         }
                     }
+                    if let Some(other) = segment_index {
+                        found.push(other);
+                    }
+                }
+                neighbors[index] = found;
+                second_neighbors[index] = second;
+            }
+        }
+
+        let links: Csr<Link> = neighbors
+            .iter()
+            .enumerate()
+            .map(|(index, found)| {
+                found
+                    .iter()
+                    .filter(|neighbor| **neighbor != index)
+                    .map(|neighbor| ret.link_between(&segment_frags, index, *neighbor))
+                    .collect()
+            })
+            .collect();
+        ret.links = links;
+        ret.seam_partners = (0..count)
+            .map(|index| {
+                ret.links[index]
+                    .iter()
+                    .filter(|link| link.kind == LinkKind::Seam)
+                    .map(|link| link.index)
+                    .collect()
+            })
+            .collect();
+
+        let mut constrained: HashSet<(usize, usize)> = HashSet::new();
+        for index in 0..count {
+            for link in &ret.links[index] {
+                if link.kind == LinkKind::Unlinked {
+                    continue;
+                }
+                let pair = (index.min(link.index), index.max(link.index));
+                if constrained.insert(pair) {
+                    ret.constraints.push(Constraint {
+                        a: pair.0,
+                        b: pair.1,
+                        kind: link.kind,
+                        rest: link.rest,
+                    });
                 }
             }
         }
 
-        let mut segment_memory: Vec<ClothSegmentFrag> = vec![];
-        for segment in &ret.segments {
-            segment_memory.push(segment.frag);
+        // only looking forward along x and z so each row of three is found once
+        for (index, ends) in second_neighbors.iter().enumerate() {
+            let cell = ret.cells[index];
+            let section = ret.segment_sections[index];
+            for &end in ends {
+                let offset = ret.cells[end] - cell;
+                if offset.x < 0 || offset.y != 0 || offset.z < 0 {
+                    continue;
+                }
+                let halfway = Index3 {
+                    x: offset.x / 2,
+                    y: 0,
+                    z: offset.z / 2,
+                };
+                let Some(&middle) = ret.grid.get(&(cell + halfway)) else {
+                    continue;
+                };
+                if ret.segment_sections[middle] != section || ret.segment_sections[end] != section {
+                    continue;
+                }
+                ret.bends.push(Bend {
+                    ends: [index, end],
+                    middle,
+                });
+            }
         }
 
-        for segment in &ret.segments {
-            // r.draw_cube(
-            //     segment.frag.position,
-            //     self.scale,
-            //     self.scale,
-            //     self.scale,
-            //     if segment.frag.pinned {
-            //         color::Color::RED
-            //     } else if segment.frag.link_number.is_some() {
-            //         color::Color::BLUE
-            //     } else {
-            //         color::Color::GREEN
-            //     },
-            // );
+        let mut segment_constraints: Vec<Vec<usize>> = vec![vec![]; count];
+        for (index, constraint) in ret.constraints.iter().enumerate() {
+            segment_constraints[constraint.a].push(index);
+            segment_constraints[constraint.b].push(index);
+        }
+        ret.segment_constraints = segment_constraints.into_iter().collect();
+        let mut segment_bends: Vec<Vec<usize>> = vec![vec![]; count];
+        for (index, bend) in ret.bends.iter().enumerate() {
+            for segment in [bend.ends[0], bend.ends[1], bend.middle] {
+                segment_bends[segment].push(index);
+            }
+        }
+        ret.segment_bends = segment_bends.into_iter().collect();
+        ret.constraint_colors = color_groups(
+            ret.constraints
+                .iter()
+                .map(|constraint| vec![constraint.a, constraint.b]),
+            count,
+        );
+        ret.bend_colors = color_groups(
+            ret.bends
+                .iter()
+                .map(|bend| vec![bend.ends[0], bend.ends[1], bend.middle]),
+            count,
+        );
+
+        for (index, found) in neighbors.iter().enumerate() {
+            let cell = ret.cells[index];
             for x_dir in [-1, 1] {
                 for y_dir in [-1, 1] {
                     let mut quad: Vec<u32> = vec![];
-                    for index in &segment.neighbor_index {
-                        let frag = segment_memory[*index];
-                        let a_value: i32 = frag.index.x - segment.frag.index.x;
-                        let b_value: i32 = frag.index.z - segment.frag.index.z;
+                    for neighbor in found {
+                        let neighbor_cell = ret.cells[*neighbor];
+                        let a_value: i32 = neighbor_cell.x - cell.x;
+                        let b_value: i32 = neighbor_cell.z - cell.z;
                         if a_value.abs() > 1 || b_value.abs() > 1 {
                             continue;
                         }
                         if a_value / x_dir >= 0 && b_value / y_dir >= 0 {
-                            quad.push(*index as u32);
+                            quad.push(*neighbor as u32);
                         }
                     }
                     if quad.len() == 4 {
@@ -405,97 +859,111 @@ impl Cloth {
                     }
                 }
             }
-
-            // r.draw_line_3D(
-            //     segment.frag.position,
-            //     segment.frag.position + segment.frag.velocity,
-            //     color::Color::RED,
-            // );
         }
+        ret.surface = ret
+            .triangles()
+            .iter()
+            .map(|triangle| triangle.map(|corner| corner as usize))
+            .filter(|triangle| {
+                let section = ret.segment_sections[triangle[0]];
+                triangle[1..]
+                    .iter()
+                    .all(|&corner| ret.segment_sections[corner] == section)
+            })
+            .collect();
 
-        sender
-            .send_blocking(Message::RenderProgress(1.0))
-            .expect("The channel needs to be open.");
+        progress.report(1.0);
 
         Some(ret)
     }
 
+    // The section a point on the flat pattern lands in. The grid only has cells inside the
+    // outline, so a point close to the edge also tries the corners of the cell around it
+    fn section_at(&self, point: Vector2, min_bound: Vector2, detail: f32) -> Option<usize> {
+        let cell = (point - min_bound) / detail;
+        let nearest = (cell.x.round() as i32, cell.y.round() as i32);
+        let corners = [
+            (cell.x.floor() as i32, cell.y.floor() as i32),
+            (cell.x.ceil() as i32, cell.y.floor() as i32),
+            (cell.x.floor() as i32, cell.y.ceil() as i32),
+            (cell.x.ceil() as i32, cell.y.ceil() as i32),
+        ];
+        std::iter::once(nearest)
+            .chain(corners)
+            .find_map(|(x, z)| self.grid.get(&Index3 { x, y: 0, z }))
+            .and_then(|&index| self.segment_sections[index])
+    }
+
+    // Flood fills from index over every touching grid cell, everything reached is one pattern
+    // piece and gets tagged with section. Uses a stack instead of recursing so big pieces
+    // dont run out of thread stack
     pub fn discover_section(
-        &self,
+        &mut self,
         index: usize,
-        current_section: Vec<Vec<usize>>,
-        sender: &Sender<Message>,
-        receiver: &Receiver<Message>,
-        length: usize,
+        section: usize,
+        progress: &mut dyn Progress,
+        cancel: &CancelToken,
         total: usize,
-    ) -> Option<Vec<Vec<usize>>> {
-        let mut current_section_copy = current_section;
-        let last_index_pos = current_section_copy.len() - 1;
-        current_section_copy[last_index_pos].push(index);
-        let cancel_check = receiver.try_recv();
-        match cancel_check {
-            Ok(Message::Back) => {
-                return None;
-            }
-            _ => {}
-        }
+    ) -> Option<Vec<usize>> {
+        let length = self.cells.len();
+        let mut members: Vec<usize> = vec![index];
+        let mut stack: Vec<usize> = vec![index];
+        self.segment_sections[index] = Some(section);
 
-        sender
-            .send_blocking(Message::RenderProgress(
-                (1.0 / 3.0) + ((current_section_copy.len() + total) as f64 / length as f64) / 3.0,
-            ))
-            .expect("The channel needs to be open.");
-
-        let selected_index = self.segments[index].frag.index;
-        'segment_search: for segment in &self.segments {
-            for section in &current_section_copy {
-                if section.contains(&segment.index) {
-                    continue 'segment_search;
-                }
+        while let Some(current) = stack.pop() {
+            if cancel.is_cancelled() {
+                return None;
             }
+            progress.report((1.0 / 3.0) + ((members.len() + total) as f64 / length as f64) / 3.0);
 
+            let selected_index = self.cells[current];
             for x in -1..=1 {
                 for y in -1..=1 {
                     for z in -1..=1 {
                         let searching_index = selected_index + Index3 { x, y, z };
-                        if segment.frag.index == searching_index {
-                            let current_section_copy_res = self.discover_section(
-                                segment.index,
-                                current_section_copy,
-                                sender,
-                                receiver,
-                                length,
-                                total,
-                            );
-                            if current_section_copy_res.is_none() {
-                                return None;
-                            }
-                            current_section_copy = current_section_copy_res.unwrap();
+                        let Some(&found) = self.grid.get(&searching_index) else {
+                            continue;
+                        };
+                        if self.segment_sections[found].is_some() {
+                            continue;
                         }
+                        self.segment_sections[found] = Some(section);
+                        members.push(found);
+                        stack.push(found);
                     }
                 }
             }
         }
-        return Some(current_section_copy);
+        Some(members)
     }
+}
 
+#[cfg(feature = "gui")]
+impl Cloth {
     pub fn draw(&self, r: &mut RaylibMode3D<'_, RaylibDrawHandle<'_>>) {
-        let mut segment_memory: Vec<ClothSegmentFrag> = vec![];
-        for segment in &self.segments {
-            segment_memory.push(segment.frag);
-        }
+        let line_color = |index: usize| {
+            if self.rigid[index] {
+                color::Color::ORANGE
+            } else if self.pinned[index] {
+                color::Color::RED
+            } else if self.seamed[index] {
+                color::Color::BLUE
+            } else {
+                color::Color::GREEN
+            }
+        };
 
         for quad in &self.quads {
             let mut last_index = *quad.last().unwrap();
             let mut traveled: Vec<u32> = vec![last_index];
             while traveled.len() != 4 {
                 for index in quad {
-                    if traveled.contains(&index) {
+                    if traveled.contains(index) {
                         continue;
                     }
 
-                    let index_pos = segment_memory[*index as usize].index;
-                    let last_index_pos = segment_memory[last_index as usize].index;
+                    let index_pos = self.cells[*index as usize];
+                    let last_index_pos = self.cells[last_index as usize];
 
                     if (index_pos.x - last_index_pos.x).abs()
                         + (index_pos.z - last_index_pos.z).abs()
@@ -505,172 +973,1444 @@ impl Cloth {
                     }
 
                     r.draw_line_3D(
-                        segment_memory[last_index as usize].position,
-                        segment_memory[*index as usize].position,
-                        if segment_memory[*index as usize].rigid {
-                            color::Color::ORANGE
-                        } else if segment_memory[*index as usize].pinned {
-                            color::Color::RED
-                        } else if segment_memory[*index as usize].link_number.is_some() {
-                            color::Color::BLUE
-                        } else {
-                            color::Color::GREEN
-                        },
+                        RVector3::from(self.positions[last_index as usize]),
+                        RVector3::from(self.positions[*index as usize]),
+                        line_color(*index as usize),
                     );
                     traveled.push(*index);
                     last_index = *index;
                 }
             }
+            let first = *traveled.first().unwrap() as usize;
+            let last = *traveled.last().unwrap() as usize;
             r.draw_line_3D(
-                segment_memory[*traveled.first().unwrap() as usize].position,
-                segment_memory[*traveled.last().unwrap() as usize].position,
-                if segment_memory[*traveled.first().unwrap() as usize].rigid {
-                    color::Color::ORANGE
-                } else if segment_memory[*traveled.first().unwrap() as usize].pinned {
-                    color::Color::RED
-                } else if segment_memory[*traveled.first().unwrap() as usize]
-                    .link_number
-                    .is_some()
-                {
-                    color::Color::BLUE
-                } else {
-                    color::Color::GREEN
-                },
+                RVector3::from(self.positions[first]),
+                RVector3::from(self.positions[last]),
+                line_color(first),
             );
         }
+        for collider in &self.colliders {
+            collider.draw(r);
+        }
+    }
+}
+
+impl Cloth {
+    // how many pattern pieces were found, numbered in the order discover_section reached them
+    pub fn section_count(&self) -> usize {
+        self.sections.len()
+    }
+
+    // meters, for keeping pieces apart when they touch
+    pub fn section_thickness(&self, section: usize) -> f32 {
+        self.thicknesses[section].unwrap_or(self.thickness)
     }
+
+    // Gives one piece its own material, None puts it back on the cloth wide one
+    pub fn set_section_material(&mut self, section: usize, material: Option<Material>) {
+        self.section_materials[section] = material;
+    }
+
+    // Moves the cloth forward by timestep, no matter how often its called
     pub fn step(&mut self) {
+        self.materials.clear();
+        self.inverse_masses.clear();
+        for (section, pinned) in self.segment_sections.iter().zip(self.pinned.iter()) {
+            let own = section.and_then(|section| self.section_materials[section]);
+            let material = own.unwrap_or(self.material);
+            self.materials.push(material);
+            // pins are constraints with no give at all, they just never move
+            self.inverse_masses.push(if *pinned {
+                0.0
+            } else {
+                DEFAULT_DENSITY / material.density
+            });
+        }
+
+        let substeps = self.substeps.max(1);
+        let dt = self.timestep / substeps as f32;
+        for _ in 0..substeps {
+            // snapshot holds where everything started so velocity can be worked out after
+            self.snapshot.clone_from(&self.positions);
+            match self.solver {
+                Solver::Springs => self.spring_substep(dt),
+                Solver::Xpbd => self.xpbd_substep(dt),
+                Solver::Implicit => self.implicit_substep(dt),
+            }
+            if self.self_collision {
+                self.collide_self(dt);
+            }
+            // last so nothing ends the substep inside one
+            if !self.colliders.is_empty() {
+                self.collide(dt);
+            }
+        }
+    }
+
+    fn xpbd_substep(&mut self, dt: f32) {
+        let mut rigid_plane = 0.0;
+        let mut rigid_len = 0;
+        for (position, rigid) in self.positions.iter().zip(self.rigid.iter()) {
+            if *rigid {
+                rigid_plane += position.y;
+                rigid_len += 1;
+            }
+        }
+        rigid_plane /= rigid_len as f32;
+        parallel::for_each(&mut self.velocities, |index, velocity| {
+            if self.pinned[index] {
+                return;
+            }
+            velocity.y -= self.gravity * dt;
+            *velocity *= (-self.materials[index].damping * dt).exp();
+        });
+        parallel::for_each(&mut self.positions, |index, position| {
+            if !self.pinned[index] {
+                *position += self.velocities[index] * dt;
+            }
+        });
+
+        let dt_squared = dt * dt;
+        self.lambdas.clear();
+        self.lambdas.resize(self.constraints.len(), 0.0);
+        self.bend_lambdas.clear();
+        self.bend_lambdas.resize(self.bends.len(), Vector3::zero());
+        let mut constraint_moves = std::mem::take(&mut self.constraint_moves);
+        let mut bend_moves = std::mem::take(&mut self.bend_moves);
+        for _ in 0..XPBD_ITERATIONS {
+            match self.update {
+                Update::Jacobi => {
+                    parallel::fill(&mut constraint_moves, self.constraints.len(), |index| {
+                        self.constraint_correction(index, dt_squared)
+                    });
+                    for (lambda, moves) in self.lambdas.iter_mut().zip(&constraint_moves) {
+                        if let Some((pushed, _, _)) = moves {
+                            *lambda += pushed;
+                        }
+                    }
+                    parallel::for_each(&mut self.positions, |index, position| {
+                        let moves =
+                            self.segment_constraints[index]
+                                .iter()
+                                .filter_map(|&constraint| {
+                                    let (_, a, b) = constraint_moves[constraint]?;
+                                    Some(if self.constraints[constraint].a == index {
+                                        a
+                                    } else {
+                                        b
+                                    })
+                                });
+                        relax(position, moves);
+                    });
+
+                    parallel::fill(&mut bend_moves, self.bends.len(), |index| {
+                        self.bend_correction(index, dt_squared)
+                    });
+                    for (lambda, moves) in self.bend_lambdas.iter_mut().zip(&bend_moves) {
+                        if let Some((pushed, _)) = moves {
+                            *lambda += *pushed;
+                        }
+                    }
+                    parallel::for_each(&mut self.positions, |index, position| {
+                        let moves = self.segment_bends[index].iter().filter_map(|&bend| {
+                            let (_, [a, b, middle]) = bend_moves[bend]?;
+                            let bend = &self.bends[bend];
+                            Some(if bend.middle == index {
+                                middle
+                            } else if bend.ends[0] == index {
+                                a
+                            } else {
+                                b
+                            })
+                        });
+                        relax(position, moves);
+                    });
+                }
+                // nothing in a group shares a segment, so the whole group is worked out before
+                // any of it is applied
+                Update::GaussSeidel => {
+                    for color in &self.constraint_colors {
+                        parallel::fill(&mut constraint_moves, color.len(), |member| {
+                            self.constraint_correction(color[member], dt_squared)
+                        });
+                        for (&index, moves) in color.iter().zip(&constraint_moves) {
+                            let Some((lambda, a, b)) = *moves else {
+                                continue;
+                            };
+                            let constraint = self.constraints[index];
+                            self.lambdas[index] += lambda;
+                            self.positions[constraint.a] += a;
+                            self.positions[constraint.b] += b;
+                        }
+                    }
+
+                    for color in &self.bend_colors {
+                        parallel::fill(&mut bend_moves, color.len(), |member| {
+                            self.bend_correction(color[member], dt_squared)
+                        });
+                        for (&index, moves) in color.iter().zip(&bend_moves) {
+                            let Some((lambda, [a, b, middle])) = *moves else {
+                                continue;
+                            };
+                            let bend = self.bends[index];
+                            self.bend_lambdas[index] += lambda;
+                            self.positions[bend.ends[0]] += a;
+                            self.positions[bend.ends[1]] += b;
+                            self.positions[bend.middle] += middle;
+                        }
+                    }
+                }
+            }
+        }
+        self.constraint_moves = constraint_moves;
+        self.bend_moves = bend_moves;
+
+        parallel::for_each(&mut self.positions, |index, position| {
+            if self.rigid[index] && !self.pinned[index] {
+                let offset = position.y - rigid_plane;
+                position.y -= offset / (1.0 + self.compliance / dt_squared);
+            }
+        });
+        parallel::for_each(&mut self.velocities, |index, velocity| {
+            if !self.pinned[index] {
+                *velocity = (self.positions[index] - self.snapshot[index]) / dt;
+            }
+        });
+    }
+
+    // How far one distance constraint wants to push its ends from where they are now, along
+    // with how much it pushed. None when it cant move either of them
+    fn constraint_correction(
+        &self,
+        index: usize,
+        dt_squared: f32,
+    ) -> Option<(f32, Vector3, Vector3)> {
+        let constraint = &self.constraints[index];
+        let material = &self.materials[constraint.a];
+        let (a_weight, b_weight) = (
+            self.inverse_masses[constraint.a],
+            self.inverse_masses[constraint.b],
+        );
+        let diff = self.positions[constraint.a] - self.positions[constraint.b];
+        let length = diff.length();
+        if a_weight + b_weight == 0.0 || length == 0.0 {
+            return None;
+        }
+        let compliance = match constraint.kind {
+            LinkKind::Seam => self.seam_compliance,
+            // compliance is for the cloth wide warp, anything weaker gives more by how much
+            // weaker it is. links with no strength at all arent held together
+            LinkKind::Section(grain) => {
+                let strength = grain.strength(
+                    material.strength,
+                    material.weft_strength,
+                    material.shear_strength,
+                );
+                if strength <= 0.0 {
+                    return None;
+                }
+                if self.material.strength > 0.0 {
+                    self.compliance * self.material.strength / strength
+                } else {
+                    self.compliance
+                }
+            }
+            LinkKind::Unlinked => self.compliance,
+        } / dt_squared;
+        let stretch = length - self.scale * constraint.rest;
+        let lambda =
+            (-stretch - compliance * self.lambdas[index]) / (a_weight + b_weight + compliance);
+        let correction = diff * (lambda / length);
+        Some((lambda, correction * a_weight, correction * -b_weight))
+    }
+
+    // The bend is a vector constraint, the middle moves twice as far as the ends. Gives the
+    // moves for both ends and then the middle
+    fn bend_correction(&self, index: usize, dt_squared: f32) -> Option<(Vector3, [Vector3; 3])> {
+        let bend = &self.bends[index];
+        let material = &self.materials[bend.middle];
+        if material.bending <= 0.0 {
+            return None;
+        }
+        let bend_compliance = 1.0 / (material.bending * dt_squared);
+        let [a_weight, b_weight] = bend.ends.map(|end| self.inverse_masses[end]);
+        let middle_weight = self.inverse_masses[bend.middle];
+        let weight = a_weight + b_weight + 4.0 * middle_weight;
+        if weight == 0.0 {
+            return None;
+        }
+        let [a, b] = bend.ends.map(|end| self.positions[end]);
+        let offset = a + b - self.positions[bend.middle] * 2.0;
+        let lambda = (offset + self.bend_lambdas[index] * bend_compliance)
+            * (-1.0 / (weight + bend_compliance));
+        Some((
+            lambda,
+            [
+                lambda * a_weight,
+                lambda * b_weight,
+                lambda * (-2.0 * middle_weight),
+            ],
+        ))
+    }
+
+    // Solves (M + dt² K) dv = dt (f + dt K v) for the change in velocity, K being how the
+    // spring forces change as points move. Fabric of the default density has a mass of 1
+    fn implicit_substep(&mut self, dt: f32) {
+        let mut buffers = std::mem::take(&mut self.implicit);
+        let count = self.positions.len();
         let mut rigid_plane = 0.0;
         let mut rigid_len = 0;
-        let mut segment_memory: Vec<ClothSegmentFrag> = vec![];
-        for segment in &self.segments {
-            if segment.frag.rigid {
-                rigid_plane += segment.frag.position.y;
+        for (position, rigid) in self.positions.iter().zip(self.rigid.iter()) {
+            if *rigid {
+                rigid_plane += position.y;
                 rigid_len += 1;
             }
-            segment_memory.push(segment.frag);
         }
         rigid_plane /= rigid_len as f32;
 
-        for segment in self.segments.iter_mut() {
-            segment.frag.velocity += Vector3 {
+        parallel::fill(&mut buffers.springs, self.constraints.len(), |index| {
+            self.implicit_spring(index, dt)
+        });
+        parallel::fill(&mut buffers.bend_pulls, self.bends.len(), |index| {
+            let bend = &self.bends[index];
+            let [a, b] = bend.ends.map(|end| self.positions[end]);
+            let offset = a + b - self.positions[bend.middle] * 2.0;
+            let [a, b] = bend.ends.map(|end| self.velocities[end]);
+            let moving = a + b - self.velocities[bend.middle] * 2.0;
+            (offset + moving * dt) * -self.materials[bend.middle].bending
+        });
+        parallel::fill(&mut buffers.rhs, count, |index| {
+            if self.pinned[index] {
+                return Vector3::zero();
+            }
+            let mut force = Vector3 {
                 x: 0.0,
                 y: -self.gravity,
                 z: 0.0,
             };
+            if self.rigid[index] {
+                force.y += (rigid_plane - self.positions[index].y) * RIGID_STRENGTH;
+            }
+            let force = force * (self.materials[index].density / DEFAULT_DENSITY)
+                + self.gather_pulls(
+                    index,
+                    |constraint| buffers.springs[constraint].1,
+                    |bend| buffers.bend_pulls[bend],
+                );
+            force * dt
+        });
 
-            let mut neighbor_forces = Vector3::zero();
-            let mut segment_section: Vec<usize> = vec![];
-            for section in &self.sections {
-                if section.contains(&segment.index) {
-                    segment_section = section.clone();
-                }
+        // conjugate gradient starting from no change
+        let dt_squared = dt * dt;
+        buffers.delta.clear();
+        buffers.delta.resize(count, Vector3::zero());
+        buffers.residual.clone_from(&buffers.rhs);
+        buffers.direction.clone_from(&buffers.rhs);
+        buffers.product.resize(count, Vector3::zero());
+        let target = CG_TOLERANCE * CG_TOLERANCE * dot_all(&buffers.rhs, &buffers.rhs);
+        let mut residual_size = dot_all(&buffers.residual, &buffers.residual);
+        for _ in 0..CG_ITERATIONS {
+            if residual_size <= target || residual_size == 0.0 {
+                break;
             }
+            self.apply_system(&mut buffers, dt_squared);
+            let curvature = dot_all(&buffers.direction, &buffers.product);
+            if curvature <= 0.0 {
+                break;
+            }
+            let step = residual_size / curvature;
+            parallel::for_each(&mut buffers.delta, |i, delta| {
+                *delta += buffers.direction[i] * step;
+            });
+            parallel::for_each(&mut buffers.residual, |i, residual| {
+                *residual -= buffers.product[i] * step;
+            });
+            let next_size = dot_all(&buffers.residual, &buffers.residual);
+            let keep = next_size / residual_size;
+            parallel::for_each(&mut buffers.direction, |i, direction| {
+                *direction = buffers.residual[i] + *direction * keep;
+            });
+            residual_size = next_size;
+        }
 
-            for index in &segment.neighbor_index {
-                if *index == segment.index {
-                    continue;
-                }
-                let frag = segment_memory[*index];
+        parallel::for_each(&mut self.velocities, |index, velocity| {
+            if self.pinned[index] {
+                *velocity = Vector3::zero();
+                return;
+            }
+            *velocity += buffers.delta[index];
+            *velocity *= (-self.materials[index].damping * dt).exp();
+        });
+        parallel::for_each(&mut self.positions, |index, position| {
+            if !self.pinned[index] {
+                *position += self.velocities[index] * dt;
+            }
+        });
+        self.implicit = buffers;
+    }
 
-                let diff = frag.position - segment.frag.position;
-                let mut dist = (frag.index - segment.frag.index).length();
-                let mut mult = self.strength;
+    // How much one spring resists being moved, and what it pulls its a end with including the
+    // damping
+    fn implicit_spring(&self, index: usize, dt: f32) -> (Block, Vector3) {
+        let constraint = &self.constraints[index];
+        let material = &self.materials[constraint.a];
+        let strength = match constraint.kind {
+            LinkKind::Seam => self.seam_strength,
+            LinkKind::Section(grain) => grain.strength(
+                material.strength,
+                material.weft_strength,
+                material.shear_strength,
+            ),
+            LinkKind::Unlinked => 0.0,
+        };
+        let rest = self.scale * constraint.rest;
+        let diff = self.positions[constraint.b] - self.positions[constraint.a];
+        let length = diff.length();
+        let direction = diff.normalized();
 
-                if segment.frag.pinned {
-                    mult = self.seam_strength;
-                }
-                if !segment_section.contains(index) {
-                    mult = 0.0;
-                }
+        let force = direction * (strength * (length - rest));
 
-                // there should be a better way
-                if segment
-                    .frag
-                    .link_number
-                    .is_some_and(|a| frag.link_number.is_some_and(|b| a == b))
-                    && frag.line_id != segment.frag.line_id
-                {
-                    dist = 0.0;
-                    mult = self.seam_strength;
-                }
+        // along the spring it pulls with the full strength, sideways only as much as its
+        // stretched. squashed springs get no sideways part so the matrix stays positive definite
+        let sideways = if length > 0.0 {
+            (1.0 - rest / length).max(0.0)
+        } else {
+            1.0
+        };
+        let along = [direction.x, direction.y, direction.z];
+        let mut jacobian: Block = [[0.0; 3]; 3];
+        for row in 0..3 {
+            for column in 0..3 {
+                let outer = along[row] * along[column];
+                let identity = if row == column { 1.0 } else { 0.0 };
+                jacobian[row][column] = strength * (outer + sideways * (identity - outer));
+            }
+        }
+
+        let relative = self.velocities[constraint.b] - self.velocities[constraint.a];
+        let damping_force = block_mul(&jacobian, relative) * dt;
+        (jacobian, force + damping_force)
+    }
+
+    // product = (M + dt² K) direction, with pinned points held still
+    fn apply_system(&self, buffers: &mut ImplicitBuffers, dt_squared: f32) {
+        let input = &buffers.direction;
+        parallel::fill(&mut buffers.pulls, self.constraints.len(), |index| {
+            let constraint = &self.constraints[index];
+            let jacobian = &buffers.springs[index].0;
+            block_mul(jacobian, input[constraint.a] - input[constraint.b]) * dt_squared
+        });
+        parallel::fill(&mut buffers.bend_pulls, self.bends.len(), |index| {
+            let bend = &self.bends[index];
+            let offset = input[bend.ends[0]] + input[bend.ends[1]] - input[bend.middle] * 2.0;
+            offset * (self.materials[bend.middle].bending * dt_squared)
+        });
+        parallel::for_each(&mut buffers.product, |index, out| {
+            *out = if self.pinned[index] {
+                Vector3::zero()
+            } else {
+                input[index] * (self.materials[index].density / DEFAULT_DENSITY)
+                    + self.gather_pulls(
+                        index,
+                        |constraint| buffers.pulls[constraint],
+                        |bend| buffers.bend_pulls[bend],
+                    )
+            };
+        });
+    }
 
-                let change = (self.scale * dist) - diff.length();
-                let scaled = diff.normalized().scale_by(-change);
-                neighbor_forces += scaled.scale_by(mult);
+    // Adds up what the springs and bends touching one segment push on it. pull gives what each
+    // constraint pushes its a end with and bend_pull what each bend pushes its ends with, the
+    // other end of a constraint gets pushed back and the middle of a bend twice as hard back
+    fn gather_pulls(
+        &self,
+        index: usize,
+        pull: impl Fn(usize) -> Vector3,
+        bend_pull: impl Fn(usize) -> Vector3,
+    ) -> Vector3 {
+        let mut total = Vector3::zero();
+        for &constraint in &self.segment_constraints[index] {
+            if self.constraints[constraint].a == index {
+                total += pull(constraint);
+            } else {
+                total -= pull(constraint);
             }
-            if segment.frag.rigid {
-                let set_pos = Vector3 {
-                    x: 0.0,
-                    y: rigid_plane - segment.frag.position.y,
-                    z: 0.0,
-                };
-                neighbor_forces += set_pos * 0.3;
+        }
+        for &bend in &self.segment_bends[index] {
+            if self.bends[bend].middle == index {
+                total -= bend_pull(bend) * 2.0;
+            } else {
+                total += bend_pull(bend);
             }
+        }
+        total
+    }
 
-            segment.frag.velocity += neighbor_forces;
+    fn spring_substep(&mut self, dt: f32) {
+        let mut rigid_plane = 0.0;
+        let mut rigid_len = 0;
+        for (position, rigid) in self.positions.iter().zip(self.rigid.iter()) {
+            if *rigid {
+                rigid_plane += position.y;
+                rigid_len += 1;
+            }
+        }
+        rigid_plane /= rigid_len as f32;
+
+        let mut accelerations = std::mem::take(&mut self.accelerations);
+        parallel::fill(&mut accelerations, self.positions.len(), |index| {
+            self.spring_acceleration(index, rigid_plane)
+        });
+        parallel::for_each(&mut self.velocities, |index, velocity| {
+            *velocity += Vector3 {
+                x: 0.0,
+                y: -self.gravity * dt,
+                z: 0.0,
+            };
+            *velocity += accelerations[index] * dt;
+            *velocity *= (-self.materials[index].damping * dt).exp();
+        });
+        parallel::for_each(&mut self.positions, |index, position| {
+            if !self.pinned[index] {
+                *position += self.velocities[index] * dt;
+            }
+        });
+        self.accelerations = accelerations;
+    }
+
+    // Everything pulling on one segment worked out from snapshot, heavier fabric gets moved
+    // less by the same pull
+    fn spring_acceleration(&self, index: usize, rigid_plane: f32) -> Vector3 {
+        let material = &self.materials[index];
+        let position = self.snapshot[index];
 
-            segment.frag.velocity *= self.drag;
+        let mut neighbor_forces = Vector3::zero();
+        for &bend_index in &self.segment_bends[index] {
+            let bend = &self.bends[bend_index];
+            let offset = self.snapshot[bend.ends[0]] + self.snapshot[bend.ends[1]]
+                - self.snapshot[bend.middle] * 2.0;
+            let force = offset * -self.materials[bend.middle].bending;
+            if bend.middle == index {
+                neighbor_forces -= force * 2.0;
+            } else {
+                neighbor_forces += force;
+            }
+        }
+        for link in &self.links[index] {
+            let mult = match link.kind {
+                LinkKind::Unlinked => continue,
+                LinkKind::Seam => self.seam_strength,
+                LinkKind::Section(_) if self.pinned[index] => self.seam_strength,
+                LinkKind::Section(grain) => grain.strength(
+                    material.strength,
+                    material.weft_strength,
+                    material.shear_strength,
+                ),
+            };
 
-            if !segment.frag.pinned {
-                segment.frag.position += segment.frag.velocity;
+            let diff = self.snapshot[link.index] - position;
+            let change = (self.scale * link.rest) - diff.length();
+            let scaled = diff.normalized().scale_by(-change);
+            neighbor_forces += scaled.scale_by(mult);
+        }
+        let mut acceleration = neighbor_forces / (material.density / DEFAULT_DENSITY);
+        if self.rigid[index] {
+            let set_pos = Vector3 {
+                x: 0.0,
+                y: rigid_plane - position.y,
+                z: 0.0,
+            };
+            acceleration += set_pos * RIGID_STRENGTH;
+        }
+        acceleration
+    }
+
+    // Pushes apart every segment that ended the substep closer to a triangle or another segment
+    // than their thicknesses, and lets friction hold back how they slide along each other. A
+    // segment that crossed a triangle during the substep is put back on the side it came from.
+    // Contacts are all found before any are applied and then pushed apart one at a time in the
+    // order of their segments, so the same cloth always untangles the same way
+    fn collide_self(&mut self, dt: f32) {
+        let mut buffers = std::mem::take(&mut self.collision);
+        buffers.pushes.clear();
+        buffers.pushes.resize(self.positions.len(), Vector3::zero());
+        for _ in 0..COLLISION_ITERATIONS {
+            self.find_all_contacts(&mut buffers);
+            if buffers.contacts.iter().all(Vec::is_empty) {
+                break;
+            }
+            for contact in buffers.contacts.iter().flatten() {
+                self.resolve_contact(contact, &mut buffers.pushes);
             }
         }
+        parallel::for_each(&mut self.velocities, |index, velocity| {
+            *velocity += buffers.pushes[index] / dt;
+        });
+        self.collision = buffers;
     }
-    fn get_neighbors(
+
+    // Files every triangle and segment in the hashes and fills in contacts for every segment
+    fn find_all_contacts(&self, buffers: &mut CollisionBuffers) {
+        // furthest apart two things can be and still touch
+        let reach = self
+            .thicknesses
+            .iter()
+            .fold(self.thickness, |reach, own| reach.max(own.unwrap_or(0.0)));
+        let margin = Vector3::new(reach, reach, reach);
+        // a segment that crossed a triangle can end up as far past it as it moved. every box
+        // gets room for segments moving as fast as the fastest one, but never more than a cell
+        // so one runaway segment cant blow every box up, the ones faster than that look along
+        // the way they moved in find_contacts
+        let slack = self
+            .positions
+            .iter()
+            .zip(&self.snapshot)
+            .fold(0.0, |moved: f32, (now, before)| {
+                moved.max((*now - *before).length())
+            })
+            .min(self.scale)
+            + reach;
+
+        buffers.triangles.clear(self.scale);
+        for (index, triangle) in self.surface.iter().enumerate() {
+            let mut min = self.positions[triangle[0]];
+            let mut max = min;
+            let mut moved: f32 = 0.0;
+            for &corner in triangle {
+                min = min.min(self.positions[corner]);
+                max = max.max(self.positions[corner]);
+                moved = moved.max((self.positions[corner] - self.snapshot[corner]).length());
+            }
+            // padded by how far its own corners moved so it covers where it was too
+            let sweep = moved + slack;
+            let sweep = Vector3::new(sweep, sweep, sweep);
+            buffers.triangles.insert(min - sweep, max + sweep, index);
+        }
+        buffers.triangles.finish();
+        buffers.segments.clear(self.scale);
+        for (index, position) in self.positions.iter().enumerate() {
+            buffers
+                .segments
+                .insert(*position - margin, *position + margin, index);
+        }
+        buffers.segments.finish();
+
+        let (triangles, segments) = (&buffers.triangles, &buffers.segments);
+        parallel::fill(&mut buffers.contacts, self.positions.len(), |index| {
+            self.find_contacts(index, triangles, segments, slack)
+        });
+    }
+
+    // Everything one segment is touching. Contacts between two segments are only listed by the
+    // lower one so each gets pushed once
+    fn find_contacts(
         &self,
-        index: Index3,
-        stiffness: u32,
-    ) -> (
-        (Vec<Index3>, Vec<usize>),
-        (Vec<Option<Index3>>, Vec<Option<usize>>),
-    ) {
-        let mut ret: Vec<Index3> = vec![];
-        let mut ret_index: Vec<usize> = vec![];
-        let mut second_ret: Vec<Option<Index3>> = vec![];
-        let mut second_ret_index: Vec<Option<usize>> = vec![];
-        let (neighbors, second_neighbors) = index.get_neighbors(stiffness);
-        for neighbor in neighbors {
-            let mut neighbor_index = 0;
-            for segment in &self.segments {
-                if segment.frag.index == neighbor {
-                    ret.push(segment.frag.index);
-                    ret_index.push(neighbor_index);
+        index: usize,
+        triangles: &SpatialHash,
+        segments: &SpatialHash,
+        slack: f32,
+    ) -> Vec<Contact> {
+        let mut contacts: Vec<Contact> = vec![];
+        let position = self.positions[index];
+        let thickness = self.segment_thickness(index);
+
+        // triangles have room for segments that moved up to slack, faster ones look along the
+        // way they came for any they crossed
+        let mut near: Vec<usize> = vec![];
+        let from = self.snapshot[index];
+        if (position - from).length() <= slack {
+            near.extend(triangles.near(position));
+        } else {
+            triangles.near_box(from.min(position), from.max(position), &mut near);
+        }
+        for triangle in near {
+            let corners = self.surface[triangle];
+            if corners.iter().any(|&corner| self.attached(index, corner)) {
+                continue;
+            }
+            let now = corners.map(|corner| self.positions[corner]);
+            let normal = (now[1] - now[0]).cross(now[2] - now[0]).normalized();
+            if normal.length() == 0.0 {
+                continue;
+            }
+            let distance = (position - now[0]).dot(normal);
+            let weights = barycentric(position - normal * distance, now);
+            if weights.iter().any(|weight| *weight < -EDGE_SLACK) {
+                continue;
+            }
+
+            // which side it started on, as long as it started over the triangle too. otherwise
+            // it came from around the edge and stays on the side its on now, see SIDE_SLACK
+            let before = corners.map(|corner| self.snapshot[corner]);
+            let before_normal = (before[1] - before[0]).cross(before[2] - before[0]);
+            let before_distance = (self.snapshot[index] - before[0]).dot(before_normal);
+            let before_weights = barycentric(
+                self.snapshot[index]
+                    - before_normal * (before_distance / before_normal.dot(before_normal)),
+                before,
+            );
+            let side = if before_distance != 0.0
+                && before_normal.dot(normal) > 0.0
+                && before_weights.iter().all(|weight| *weight >= -SIDE_SLACK)
+            {
+                before_distance.signum()
+            } else if distance < 0.0 {
+                -1.0
+            } else {
+                1.0
+            };
+
+            let gap = (thickness + self.segment_thickness(corners[0])) / 2.0;
+            if distance * side >= gap {
+                continue;
+            }
+            contacts.push(Contact {
+                segment: index,
+                corners,
+                weights,
+                normal: normal * side,
+                gap,
+            });
+        }
+
+        for other in segments.near(position) {
+            if other <= index || self.attached(index, other) {
+                continue;
+            }
+            let offset = position - self.positions[other];
+            let distance = offset.length();
+            let gap = (thickness + self.segment_thickness(other)) / 2.0;
+            if distance >= gap || distance == 0.0 {
+                continue;
+            }
+            contacts.push(Contact {
+                segment: index,
+                corners: [other; 3],
+                weights: [1.0, 0.0, 0.0],
+                normal: offset / distance,
+                gap,
+            });
+        }
+        contacts
+    }
+
+    // Splits the push that clears one contact between its segment and the corners it touched,
+    // heavier and pinned ones moving less, then takes away as much of the sliding between them
+    // this substep as friction allows. Friction is the average of both fabrics
+    fn resolve_contact(&mut self, contact: &Contact, pushes: &mut [Vector3]) {
+        // each corner with how much of the touching point it makes up and how easily it moves
+        let corners: Vec<(usize, f32, f32)> = contact
+            .corners
+            .iter()
+            .zip(contact.weights)
+            .filter(|(_, share)| *share != 0.0)
+            .map(|(&corner, share)| (corner, share, self.inverse_masses[corner]))
+            .collect();
+        let segment_weight = self.inverse_masses[contact.segment];
+        let weight = segment_weight
+            + corners
+                .iter()
+                .map(|(_, share, weight)| share * share * weight)
+                .sum::<f32>();
+        let mut touching = self.positions[contact.segment];
+        for &(corner, share, _) in &corners {
+            touching -= self.positions[corner] * share;
+        }
+        let depth = contact.gap - touching.dot(contact.normal);
+        if weight == 0.0 || depth <= 0.0 {
+            return;
+        }
+
+        let moved = |index: usize| self.positions[index] - self.snapshot[index];
+        let mut sliding = moved(contact.segment);
+        for &(corner, share, _) in &corners {
+            sliding -= moved(corner) * share;
+        }
+        sliding -= contact.normal * sliding.dot(contact.normal);
+        let friction = (self.materials[contact.segment].friction
+            + self.materials[contact.corners[0]].friction)
+            / 2.0;
+        let slid = sliding.length();
+        let held = if slid > 0.0 {
+            (friction * depth / slid).min(1.0)
+        } else {
+            0.0
+        };
+        let push = (contact.normal * depth - sliding * held) / weight;
+
+        let mut add = |index: usize, amount: Vector3| {
+            self.positions[index] += amount;
+            pushes[index] += amount;
+        };
+        add(contact.segment, push * segment_weight);
+        for (corner, share, weight) in corners {
+            add(corner, push * -(share * weight));
+        }
+    }
+
+    // Puts every segment that ended the substep inside a collider, or closer to it than half its
+    // thickness, back on the surface. Each segment only moves itself so they all go at once
+    fn collide(&mut self, dt: f32) {
+        let mut pushes = std::mem::take(&mut self.collision.pushes);
+        parallel::fill(&mut pushes, self.positions.len(), |index| {
+            self.collider_push(index)
+        });
+        parallel::for_each(&mut self.positions, |index, position| {
+            *position += pushes[index];
+        });
+        parallel::for_each(&mut self.velocities, |index, velocity| {
+            *velocity += pushes[index] / dt;
+        });
+        self.collision.pushes = pushes;
+    }
+
+    // How far one segment has to move to be clear of every collider, taking away as much of its
+    // sliding along them this substep as friction allows
+    fn collider_push(&self, index: usize) -> Vector3 {
+        if self.inverse_masses[index] == 0.0 {
+            return Vector3::zero();
+        }
+        let start = self.positions[index];
+        let gap = self.segment_thickness(index) / 2.0;
+        let mut position = start;
+        for collider in &self.colliders {
+            let (distance, normal) = collider.distance(position);
+            let depth = gap - distance;
+            if depth <= 0.0 {
+                continue;
+            }
+            let mut sliding = position - self.snapshot[index];
+            sliding -= normal * sliding.dot(normal);
+            let friction = (collider.friction + self.materials[index].friction) / 2.0;
+            let slid = sliding.length();
+            let held = if slid > 0.0 {
+                (friction * depth / slid).min(1.0)
+            } else {
+                0.0
+            };
+            position += normal * depth - sliding * held;
+        }
+        position - start
+    }
+
+    // Segments close by on the flat pattern, or sewn to something close by, are held where they
+    // should be by their links so they dont collide
+    fn attached(&self, a: usize, b: usize) -> bool {
+        let close = |b: usize| {
+            let offset = self.cells[a] - self.cells[b];
+            self.segment_sections[a] == self.segment_sections[b]
+                && offset.x.abs() <= COLLISION_CELLS
+                && offset.z.abs() <= COLLISION_CELLS
+        };
+        close(b) || self.seam_partners[b].iter().any(|&partner| close(partner))
+    }
+
+    fn segment_thickness(&self, index: usize) -> f32 {
+        self.segment_sections[index]
+            .map_or(self.thickness, |section| self.section_thickness(section))
+    }
+
+    // seams win over sections so linked edges of separate pieces still get sewn together
+    fn link_between(&self, frags: &[ClothSegmentFrag], from: usize, to: usize) -> Link {
+        let segment = frags[from];
+        let frag = frags[to];
+        // there should be a better way
+        let kind = if segment
+            .link_number
+            .is_some_and(|a| frag.link_number.is_some_and(|b| a == b))
+            && frag.line_id != segment.line_id
+        {
+            LinkKind::Seam
+        } else if self.segment_sections[to] == self.segment_sections[from] {
+            LinkKind::Section(self.grain_between(from, to))
+        } else {
+            LinkKind::Unlinked
+        };
+        let rest = if kind == LinkKind::Seam {
+            0.0
+        } else {
+            (frag.index - segment.index).length()
+        };
+        Link {
+            index: to,
+            kind,
+            rest,
+        }
+    }
+
+    // compares the link on the flat pattern against the warp of the piece it is in
+    fn grain_between(&self, from: usize, to: usize) -> Grain {
+        let offset = self.cells[to] - self.cells[from];
+        let offset = Vector2 {
+            x: offset.x as f32,
+            y: offset.z as f32,
+        };
+        let warp =
+            self.segment_sections[from].map_or(DEFAULT_GRAIN, |section| self.grains[section]);
+        // cos and sin of the angle between them, the bias peaks at 45 degrees
+        let along = offset.dot(warp) / offset.length();
+        let across = (1.0 - along * along).max(0.0).sqrt();
+        let shear = (2.0 * along * across).abs();
+        Grain {
+            weft: (1.0 - shear) * across * across,
+            shear,
+        }
+    }
+
+    pub fn segment_count(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn positions(&self) -> Vec<Vector3> {
+        self.positions.clone()
+    }
+
+    // Every quad once with its corners going around the edge, ready to be used as mesh faces
+    pub fn faces(&self) -> Vec<[u32; 4]> {
+        let mut seen: HashSet<[u32; 4]> = HashSet::new();
+        let mut faces: Vec<[u32; 4]> = vec![];
+        for quad in &self.quads {
+            let mut key = [quad[0], quad[1], quad[2], quad[3]];
+            key.sort();
+            if !seen.insert(key) {
+                continue;
+            }
+
+            let min_x = key.iter().map(|i| self.cells[*i as usize].x).min().unwrap();
+            let min_z = key.iter().map(|i| self.cells[*i as usize].z).min().unwrap();
+            let mut face = key;
+            for i in key {
+                let index = self.cells[i as usize];
+                let corner = match (index.x - min_x, index.z - min_z) {
+                    (0, 0) => 0,
+                    (1, 0) => 1,
+                    (1, 1) => 2,
+                    _ => 3,
+                };
+                face[corner] = i;
+            }
+            faces.push(face);
+        }
+        faces
+    }
+
+    // Each face split into two triangles for formats that dont take quads
+    pub fn triangles(&self) -> Vec<[u32; 3]> {
+        let mut triangles: Vec<[u32; 3]> = vec![];
+        for face in self.faces() {
+            triangles.push([face[0], face[1], face[2]]);
+            triangles.push([face[0], face[2], face[3]]);
+        }
+        triangles
+    }
+
+    // Texture coordinates from where each segment sat on the flat pattern, both axes are
+    // divided by the same amount so a square of fabric stays square in the texture
+    pub fn uvs(&self) -> Vec<Vector2> {
+        let mut uvs: Vec<Vector2> = vec![];
+        if self.cells.is_empty() {
+            return uvs;
+        }
+        let min_x = self.cells.iter().map(|cell| cell.x).min().unwrap();
+        let max_x = self.cells.iter().map(|cell| cell.x).max().unwrap();
+        let min_z = self.cells.iter().map(|cell| cell.z).min().unwrap();
+        let max_z = self.cells.iter().map(|cell| cell.z).max().unwrap();
+        let extent = (max_x - min_x).max(max_z - min_z).max(1) as f32;
+        for cell in &self.cells {
+            // the pattern's y points down but v points up
+            uvs.push(Vector2 {
+                x: (cell.x - min_x) as f32 / extent,
+                y: 1.0 - (cell.z - min_z) as f32 / extent,
+            });
+        }
+        uvs
+    }
+
+    // fastest moving free segment in meters per second, used to tell when the cloth has settled
+    pub fn max_speed(&self) -> f32 {
+        let mut max_speed: f32 = 0.0;
+        for (velocity, pinned) in self.velocities.iter().zip(self.pinned.iter()) {
+            if !pinned {
+                max_speed = max_speed.max(velocity.length());
+            }
+        }
+        max_speed
+    }
+
+    // The segments within stiffness cells of cell including the one there, and the ones two
+    // cells away along each axis
+    fn get_neighbors(&self, cell: Index3) -> (Vec<usize>, Vec<usize>) {
+        let (neighbors, second_neighbors) = cell.get_neighbors(self.stiffness);
+        let found = |cells: Vec<Index3>| {
+            cells
+                .iter()
+                .filter_map(|neighbor| self.grid.get(neighbor).copied())
+                .collect()
+        };
+        (found(neighbors), found(second_neighbors))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // two 10cm squares in 2cm segments, the second starting x cm along. with seam the right edge
+    // of the first is linked to the left edge of the second. stiffness is how many cells links reach
+    fn squares(x: f32, seam: bool, stiffness: u32) -> Cloth {
+        let mut draft = Draft::from_svg(&format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="40cm" height="10cm" viewBox="0 0 40 10">
+                <rect x="0" y="0" width="10" height="10"/>
+                <rect x="{}" y="0" width="10" height="10"/>
+            </svg>"#,
+            x
+        ));
+        if seam {
+            for line in &mut draft.lines {
+                if line.p1.x == line.p2.x && (line.p1.x == 10.0 || line.p1.x == x) {
+                    line.link = Some(1);
                 }
-                neighbor_index += 1;
             }
         }
-        'optional_loop: for neighbor in &ret {
-            let mut neighbor_index = 0;
-            for segment in &self.segments {
-                if segment.frag.index == *neighbor {
-                    second_ret.push(Some(segment.frag.index));
-                    second_ret_index.push(Some(neighbor_index));
-                    continue 'optional_loop;
+        let settings = GenerateSettings {
+            scale: 0.02,
+            detail: draft.centimeters_to_units(2.0),
+            stiffness,
+            gravity: 9.81,
+            material: Material::default(),
+            seam_strength: 200.0 * 25.0,
+        };
+        Cloth::generate_from_draft(&draft, &settings, &mut |_: f64| {}, &CancelToken::new())
+            .unwrap()
+    }
+
+    fn two_squares() -> Cloth {
+        squares(20.0, false, 2)
+    }
+
+    #[test]
+    fn sections_stop_at_gaps_and_seams() {
+        // a single empty cell between them, and sewn together further apart
+        for (cloth, sewn) in [
+            (squares(12.0, false, 2), false),
+            (squares(20.0, true, 2), true),
+        ] {
+            let seamed =
+                (0..cloth.segment_count()).any(|index| !cloth.seam_partners[index].is_empty());
+            assert_eq!(seamed, sewn);
+            assert_eq!(cloth.section_count(), 2);
+            for section in &cloth.sections {
+                assert_eq!(section.len(), 25);
+                let first = cloth.cells[section[0]].x < 6;
+                for &index in section {
+                    assert_eq!(cloth.cells[index].x < 6, first);
                 }
-                neighbor_index += 1;
             }
+        }
+    }
 
-            second_ret.push(None);
-            second_ret_index.push(None);
+    #[test]
+    fn grid_neighbors_match_a_full_scan() {
+        let cloth = two_squares();
+        let scan = |cells: &[Index3]| -> Vec<usize> {
+            (0..cloth.cells.len())
+                .filter(|&index| cells.contains(&cloth.cells[index]))
+                .collect()
+        };
+        for &cell in &cloth.cells {
+            let (near, far) = cell.get_neighbors(cloth.stiffness);
+            let (mut found, mut second) = cloth.get_neighbors(cell);
+            found.sort();
+            second.sort();
+            assert_eq!(found, scan(&near));
+            assert_eq!(second, scan(&far));
+            assert!(found.len() > 1);
         }
-        ((ret, ret_index), (second_ret, second_ret_index))
     }
 
-    fn find_index_info(
-        &mut self,
-        index: Index3,
-    ) -> Option<(
-        &mut ClothSegment,
-        (Vec<Index3>, Vec<usize>),
-        (Vec<Option<Index3>>, Vec<Option<usize>>),
-    )> {
-        let (neighbors, second_neighbors) = self.get_neighbors(index, self.stiffness);
-        for segment in self.segments.iter_mut() {
-            if segment.frag.index == index {
-                return Some((segment, neighbors, second_neighbors));
-            }
-        }
-        return None;
+    #[test]
+    fn links_know_seams_from_sections() {
+        // (sewn, close) links between the pieces of each cloth
+        let count = |cloth: &Cloth| {
+            let mut between = (0, 0);
+            for index in 0..cloth.segment_count() {
+                let section = cloth.segment_sections[index];
+                for link in &cloth.links[index] {
+                    let same = cloth.segment_sections[link.index] == section;
+                    match link.kind {
+                        LinkKind::Section(_) => assert!(same),
+                        LinkKind::Seam => {
+                            assert!(!same);
+                            assert_eq!(link.rest, 0.0);
+                            // both edges run the same way so each row is sewn to its own row
+                            assert_eq!(cloth.cells[index].z, cloth.cells[link.index].z);
+                            between.0 += 1;
+                        }
+                        LinkKind::Unlinked => {
+                            assert!(!same);
+                            between.1 += 1;
+                        }
+                    }
+                }
+            }
+            between
+        };
+        // a seam along the whole edge, listed from both sides
+        assert_eq!(count(&squares(20.0, true, 2)), (10, 0));
+        // close enough to be neighbors but not sewn
+        let (seams, unlinked) = count(&squares(12.0, false, 2));
+        assert_eq!(seams, 0);
+        assert!(unlinked > 0);
+    }
+
+    // pins the top row of both squares, lets them hang for two seconds and gives back where
+    // everything started
+    fn hang(solver: Solver, timestep: f32, material: Material) -> (Cloth, Vec<Vector3>) {
+        let mut cloth = two_squares();
+        cloth.solver = solver;
+        cloth.material = material;
+        let top = cloth.cells.iter().map(|cell| cell.z).min().unwrap();
+        hang_by(cloth, timestep, |cloth, index| cloth.cells[index].z == top)
+    }
+
+    fn hang_by(
+        mut cloth: Cloth,
+        timestep: f32,
+        pin: impl Fn(&Cloth, usize) -> bool,
+    ) -> (Cloth, Vec<Vector3>) {
+        cloth.timestep = timestep;
+        for index in 0..cloth.segment_count() {
+            cloth.pinned[index] = pin(&cloth, index);
+        }
+        let start = cloth.positions();
+        for _ in 0..(2.0 / timestep).round() as u32 {
+            cloth.step();
+        }
+        (cloth, start)
+    }
+
+    fn assert_hanging(cloth: &Cloth, start: &[Vector3]) {
+        let highest = start
+            .iter()
+            .map(|position| position.y)
+            .fold(f32::MIN, f32::max);
+        let lowest = cloth
+            .positions
+            .iter()
+            .map(|position| position.y)
+            .fold(f32::MAX, f32::min);
+        // swung down from flat and settled there
+        assert!(lowest < highest - 0.05);
+        assert!(cloth.max_speed() < 0.05);
+        for (index, position) in cloth.positions.iter().enumerate() {
+            assert!(position.x.is_finite() && position.y.is_finite() && position.z.is_finite());
+            if cloth.pinned[index] {
+                assert_eq!(*position, start[index]);
+            } else {
+                // falling freely would have dropped it twenty meters by now
+                assert!((*position - start[index]).length() < 0.2, "{:?}", position);
+                assert!(position.y < highest + 0.01);
+            }
+        }
+    }
+
+    #[test]
+    fn xpbd_sheet_hangs_still() {
+        let (cloth, start) = hang(Solver::Xpbd, DEFAULT_TIMESTEP, Material::default());
+        assert_hanging(&cloth, &start);
+    }
+
+    #[test]
+    fn implicit_sheet_hangs_still() {
+        let (cloth, start) = hang(Solver::Implicit, DEFAULT_TIMESTEP, Material::default());
+        assert_hanging(&cloth, &start);
+        // a hundred times stiffer with only five steps a second
+        let stiff = Material {
+            strength: 20000.0,
+            weft_strength: 20000.0,
+            shear_strength: 20000.0,
+            ..Material::default()
+        };
+        let (cloth, start) = hang(Solver::Implicit, 0.2, stiff);
+        assert_hanging(&cloth, &start);
+    }
+
+    #[test]
+    fn threads_stretch_by_their_own_strength() {
+        let cloth = two_squares();
+        let at = |x, z| cloth.grid[&Index3 { x, y: 0, z }];
+        // the warp runs down the page, which is z on the grid
+        assert_eq!(
+            cloth.grain_between(at(1, 1), at(1, 2)),
+            Grain {
+                weft: 0.0,
+                shear: 0.0
+            }
+        );
+        assert_eq!(
+            cloth.grain_between(at(1, 1), at(2, 1)),
+            Grain {
+                weft: 1.0,
+                shear: 0.0
+            }
+        );
+        let bias = cloth.grain_between(at(1, 1), at(2, 2));
+        assert!(bias.weft.abs() < 1e-5 && (bias.shear - 1.0).abs() < 1e-5);
+
+        // how far the squares sag hung from their top edges and from their left edges
+        let sags = |solver: Solver, material: Material| {
+            [false, true].map(|side| {
+                let mut cloth = two_squares();
+                cloth.solver = solver;
+                cloth.material = material;
+                // xpbd links dont give at all without some compliance
+                cloth.compliance = 0.001;
+                let top = cloth.cells.iter().map(|cell| cell.z).min().unwrap();
+                let (cloth, start) = hang_by(cloth, DEFAULT_TIMESTEP, |cloth, index| {
+                    if side {
+                        let section = cloth.segment_sections[index];
+                        let left = (0..cloth.segment_count())
+                            .filter(|&other| cloth.segment_sections[other] == section)
+                            .map(|other| cloth.cells[other].x)
+                            .min()
+                            .unwrap();
+                        cloth.cells[index].x == left
+                    } else {
+                        cloth.cells[index].z == top
+                    }
+                });
+                let highest = start
+                    .iter()
+                    .map(|position| position.y)
+                    .fold(f32::MIN, f32::max);
+                let lowest = cloth
+                    .positions
+                    .iter()
+                    .map(|position| position.y)
+                    .fold(f32::MAX, f32::min);
+                highest - lowest
+            })
+        };
+        for solver in [Solver::Springs, Solver::Xpbd, Solver::Implicit] {
+            let [top, side] = sags(solver, Material::default());
+            assert!((top - side).abs() < 0.001, "{:?}", solver);
+            let weak_warp = Material {
+                strength: 20.0,
+                ..Material::default()
+            };
+            let [warp_top, warp_side] = sags(solver, weak_warp);
+            assert!(warp_top > warp_side + 0.005, "{:?}", solver);
+            let weak_weft = Material {
+                weft_strength: 20.0,
+                ..Material::default()
+            };
+            let [weft_top, weft_side] = sags(solver, weak_weft);
+            assert!(weft_side > weft_top + 0.005, "{:?}", solver);
+            // the bias runs the same way both hangs so a weak one lets them both drop further
+            let weak_shear = Material {
+                shear_strength: 20.0,
+                ..Material::default()
+            };
+            let [shear_top, shear_side] = sags(solver, weak_shear);
+            assert!(
+                shear_top > top + 0.01 && shear_side > side + 0.01,
+                "{:?}",
+                solver
+            );
+        }
+    }
+
+    #[test]
+    fn jacobi_keeps_mirrored_pieces_mirrored() {
+        // the squares mirror each other across the middle of the page, so every segment has a
+        // twin that should move the same way with x flipped
+        for solver in [Solver::Springs, Solver::Xpbd, Solver::Implicit] {
+            let mut cloth = two_squares();
+            cloth.solver = solver;
+            cloth.update = Update::Jacobi;
+            cloth.compliance = 0.001;
+            let top = cloth.cells.iter().map(|cell| cell.z).min().unwrap();
+            let (cloth, start) = hang_by(cloth, DEFAULT_TIMESTEP, |cloth, index| {
+                cloth.cells[index].z == top
+            });
+            let left = cloth.cells.iter().map(|cell| cell.x).min().unwrap();
+            let right = cloth.cells.iter().map(|cell| cell.x).max().unwrap();
+            // twice the x of the middle, the pinned corners dont move
+            let sides = start
+                .iter()
+                .map(|position| position.x)
+                .fold(f32::MAX, f32::min)
+                + start
+                    .iter()
+                    .map(|position| position.x)
+                    .fold(f32::MIN, f32::max);
+            for (index, cell) in cloth.cells.iter().enumerate() {
+                let twin = cloth.grid[&Index3 {
+                    x: left + right - cell.x,
+                    ..*cell
+                }];
+                let position = cloth.positions[index];
+                let mirrored = cloth.positions[twin];
+                assert!(
+                    (position.x + mirrored.x - sides).abs() < 1e-5,
+                    "{:?}",
+                    solver
+                );
+                assert!((position.y - mirrored.y).abs() < 1e-5, "{:?}", solver);
+                assert!((position.z - mirrored.z).abs() < 1e-5, "{:?}", solver);
+            }
+        }
+    }
+
+    // with the parallel feature on, splitting the work up more ways shouldnt move a single bit
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn threads_dont_change_steps() {
+        // fine enough to be handed out to several threads
+        let draft = Draft::from_svg(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="20cm" height="20cm" viewBox="0 0 20 20">
+                <rect x="0" y="0" width="20" height="20"/>
+            </svg>"#,
+        );
+        let settings = GenerateSettings {
+            scale: 0.02,
+            detail: draft.centimeters_to_units(1.0),
+            stiffness: 2,
+            gravity: 9.81,
+            material: Material::default(),
+            seam_strength: 200.0 * 25.0,
+        };
+        let run = |threads: usize, solver: Solver, update: Update| {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            pool.install(|| {
+                let mut cloth = Cloth::generate_from_draft(
+                    &draft,
+                    &settings,
+                    &mut |_: f64| {},
+                    &CancelToken::new(),
+                )
+                .unwrap();
+                cloth.solver = solver;
+                cloth.update = update;
+                cloth.compliance = 0.001;
+                let top = cloth.cells.iter().map(|cell| cell.z).min().unwrap();
+                for index in 0..cloth.segment_count() {
+                    cloth.pinned[index] = cloth.cells[index].z == top;
+                }
+                for _ in 0..10 {
+                    cloth.step();
+                }
+                cloth.positions
+            })
+        };
+        for (solver, update) in [
+            (Solver::Springs, Update::Jacobi),
+            (Solver::Xpbd, Update::Jacobi),
+            (Solver::Xpbd, Update::GaussSeidel),
+            (Solver::Implicit, Update::Jacobi),
+        ] {
+            assert_eq!(
+                run(1, solver, update),
+                run(4, solver, update),
+                "{:?}",
+                solver
+            );
+        }
+    }
+
+    #[test]
+    fn bending_flattens_a_fold() {
+        // how far the middle of each row of three is off the line between its ends
+        let crease = |cloth: &Cloth| {
+            cloth
+                .bends
+                .iter()
+                .map(|bend| {
+                    let ends = cloth.positions[bend.ends[0]] + cloth.positions[bend.ends[1]];
+                    (cloth.positions[bend.middle] - ends * 0.5).length()
+                })
+                .fold(0.0, f32::max)
+        };
+        for solver in [Solver::Springs, Solver::Xpbd, Solver::Implicit] {
+            for bending in [0.0, Material::default().bending] {
+                // links only reach one cell, so folding along a row doesnt stretch any of them
+                let mut cloth = squares(20.0, false, 1);
+                cloth.solver = solver;
+                cloth.gravity = 0.0;
+                cloth.material.bending = bending;
+                let fold = 3;
+                let fold_z = cloth.positions[cloth.grid[&Index3 {
+                    x: 1,
+                    y: 0,
+                    z: fold,
+                }]]
+                    .z;
+                for index in 0..cloth.segment_count() {
+                    if cloth.cells[index].z > fold {
+                        let past = cloth.positions[index].z - fold_z;
+                        cloth.positions[index].z = fold_z + past * 0.5_f32.cos();
+                        cloth.positions[index].y += past * 0.5_f32.sin();
+                    }
+                }
+                let start = crease(&cloth);
+                assert!(start > 0.001);
+                for _ in 0..60 {
+                    cloth.step();
+                }
+                if bending > 0.0 {
+                    assert!(crease(&cloth) < start * 0.1, "{:?}", solver);
+                } else {
+                    assert!(
+                        (crease(&cloth) - start).abs() < start * 0.01,
+                        "{:?}",
+                        solver
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fast_sheets_still_touch() {
+        let mut cloth = two_squares();
+        assert_eq!(cloth.section_count(), 2);
+        cloth.thickness = 0.002;
+        // lay the second square a millimeter over the first
+        let left = |cloth: &Cloth, section: usize| {
+            (0..cloth.positions.len())
+                .filter(|&index| cloth.segment_sections[index] == Some(section))
+                .map(|index| cloth.positions[index].x)
+                .fold(f32::INFINITY, f32::min)
+        };
+        let shift = Vector3::new(left(&cloth, 0) - left(&cloth, 1), 0.001, 0.0);
+        for index in 0..cloth.positions.len() {
+            if cloth.segment_sections[index] == Some(1) {
+                cloth.positions[index] += shift;
+            }
+        }
+        // both fell ten meters this substep, far more cells than any box gets filed under
+        cloth.snapshot = cloth
+            .positions
+            .iter()
+            .map(|position| *position + Vector3::new(0.0, 10.0, 0.0))
+            .collect();
+
+        let mut buffers = CollisionBuffers::default();
+        cloth.find_all_contacts(&mut buffers);
+        // against triangles of the other square, not just the segments right under them
+        let between = buffers
+            .contacts
+            .iter()
+            .flatten()
+            .filter(|contact| {
+                contact.corners[0] != contact.corners[1]
+                    && cloth.segment_sections[contact.segment]
+                        != cloth.segment_sections[contact.corners[0]]
+            })
+            .count();
+        assert!(between > 0);
     }
 }
@@ -0,0 +1,133 @@
+#[cfg(feature = "gui")]
+use raylib::prelude::{
+    RaylibDraw3D, RaylibDrawHandle, RaylibMode3D, Vector2 as RVector2, Vector3 as RVector3, color,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::math::Vector3;
+
+// how much the cloth grips a collider unless told otherwise
+pub const DEFAULT_FRICTION: f32 = 0.5;
+
+// Something solid that never moves, the cloth stays outside of it and slides over it as much as
+// friction lets it
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct Collider {
+    #[serde(flatten)]
+    pub shape: Shape,
+    // averaged with the friction of the fabric touching it, 0 slides freely
+    pub friction: f32,
+}
+
+// Meters, in the same space as the cloth which starts out 1 meter up
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(tag = "shape", rename_all = "snake_case")]
+pub enum Shape {
+    // a floor with everything below height solid
+    Ground {
+        height: f32,
+    },
+    Sphere {
+        center: Vector3,
+        radius: f32,
+    },
+    // a cylinder from start to end with rounded ends, a skirt drapes over one standing up
+    Capsule {
+        start: Vector3,
+        end: Vector3,
+        radius: f32,
+    },
+    // lined up with the axes, size is the whole width, height and depth
+    Box {
+        center: Vector3,
+        size: Vector3,
+    },
+}
+
+impl Collider {
+    pub fn new(shape: Shape) -> Self {
+        Collider {
+            shape,
+            friction: DEFAULT_FRICTION,
+        }
+    }
+
+    // How far point is outside the surface, negative once it is inside, and which way is out
+    pub fn distance(&self, point: Vector3) -> (f32, Vector3) {
+        match self.shape {
+            Shape::Ground { height } => (point.y - height, Vector3::new(0.0, 1.0, 0.0)),
+            Shape::Sphere { center, radius } => away(point - center, radius),
+            Shape::Capsule { start, end, radius } => {
+                let axis = end - start;
+                let length = axis.dot(axis);
+                // how far along the axis the closest point is, the ends are half spheres
+                let along = if length > 0.0 {
+                    ((point - start).dot(axis) / length).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+                away(point - (start + axis * along), radius)
+            }
+            Shape::Box { center, size } => {
+                let offset = point - center;
+                let offset = [offset.x, offset.y, offset.z];
+                let half = [size.x / 2.0, size.y / 2.0, size.z / 2.0];
+                let outside: [f32; 3] = std::array::from_fn(|axis| offset[axis].abs() - half[axis]);
+                if outside.iter().any(|past| *past > 0.0) {
+                    // out towards the closest point on the surface, a face, edge or corner
+                    let past: [f32; 3] =
+                        std::array::from_fn(|axis| outside[axis].max(0.0) * offset[axis].signum());
+                    let past = Vector3::new(past[0], past[1], past[2]);
+                    let distance = past.length();
+                    (distance, past / distance)
+                } else {
+                    // inside, out through whichever face is closest
+                    let axis = (0..3)
+                        .max_by(|a, b| outside[*a].total_cmp(&outside[*b]))
+                        .unwrap();
+                    let mut normal = [0.0; 3];
+                    normal[axis] = offset[axis].signum();
+                    (outside[axis], Vector3::new(normal[0], normal[1], normal[2]))
+                }
+            }
+        }
+    }
+}
+
+// distance from the surface of something round, offset being from its middle
+fn away(offset: Vector3, radius: f32) -> (f32, Vector3) {
+    let length = offset.length();
+    if length == 0.0 {
+        // right in the middle every way is as good, up lets the cloth fall back on top
+        return (-radius, Vector3::new(0.0, 1.0, 0.0));
+    }
+    (length - radius, offset / length)
+}
+
+#[cfg(feature = "gui")]
+impl Collider {
+    pub fn draw(&self, r: &mut RaylibMode3D<'_, RaylibDrawHandle<'_>>) {
+        let color = color::Color::GRAY;
+        match self.shape {
+            Shape::Ground { height } => r.draw_plane(
+                RVector3::new(0.0, height, 0.0),
+                RVector2::new(10.0, 10.0),
+                color::Color::LIGHTGRAY,
+            ),
+            Shape::Sphere { center, radius } => {
+                r.draw_sphere_wires(RVector3::from(center), radius, 16, 16, color)
+            }
+            Shape::Capsule { start, end, radius } => r.draw_capsule_wires(
+                RVector3::from(start),
+                RVector3::from(end),
+                radius,
+                16,
+                8,
+                color,
+            ),
+            Shape::Box { center, size } => {
+                r.draw_cube_wires(RVector3::from(center), size.x, size.y, size.z, color)
+            }
+        }
+    }
+}
@@ -1,14 +1,32 @@
+use crate::material::{Material, Preset};
+use crate::math::Vector2;
+use crate::svg;
 use core::f32;
+#[cfg(feature = "gui")]
 use raylib::{
     camera::Camera2D,
     color::Color,
-    math::Vector2,
+    math::Vector2 as RVector2,
     prelude::{RaylibDraw, RaylibDrawHandle, RaylibMode2DExt},
 };
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use xml::escape::escape_str_attribute;
 use xml::reader::{EventReader, XmlEvent};
 
+// millimeters a flattened curve may stray from the real one unless told otherwise
+pub const DEFAULT_TOLERANCE: f32 = 0.5;
+
+// what the settings start the cloth wide thickness at, in millimeters
+pub const DEFAULT_THICKNESS: f32 = 0.5;
+
+// pin, rigid, link and grainline annotations are stored under this namespace, like librecad does with lc:
+pub const NAMESPACE: &str = "https://foxmoss.org/weaverling";
+const PREFIX: &str = "weaverling";
+
 #[derive(PartialEq, Clone, Copy)]
 pub struct Line {
     pub p1: Vector2,
@@ -20,6 +38,59 @@ pub struct Line {
     pub line_id: usize,
 }
 
+// The arrow on a pattern piece showing which way the warp threads run, from p1 towards p2.
+// It isnt part of the outline so it never gets rasterized
+#[derive(PartialEq, Clone, Copy)]
+pub struct Grainline {
+    pub p1: Vector2,
+    pub p2: Vector2,
+}
+
+impl Grainline {
+    pub fn middle(&self) -> Vector2 {
+        (self.p1 + self.p2) * 0.5
+    }
+    pub fn direction(&self) -> Vector2 {
+        (self.p2 - self.p1).normalized()
+    }
+}
+
+// What one pattern piece is made of and how it sits, found by whichever closed outline its
+// anchor lands inside. Pieces nobody has set anything on dont need one of these
+#[derive(PartialEq, Clone)]
+pub struct Piece {
+    pub anchor: Vector2,
+    // None is made of whatever the settings say
+    pub material: Option<Preset>,
+    // millimeters, None is as thick as the settings say
+    pub thickness: Option<f32>,
+    // pieces on higher layers start out above lower ones so stacked pieces dont start tangled
+    pub layer: i32,
+    // degrees the warp is turned from running down the page, None follows the grainline arrow
+    pub grain_angle: Option<f32>,
+}
+
+impl Piece {
+    pub fn new(anchor: Vector2) -> Piece {
+        Piece {
+            anchor,
+            material: None,
+            thickness: None,
+            layer: 0,
+            grain_angle: None,
+        }
+    }
+    pub fn is_default(&self) -> bool {
+        *self == Piece::new(self.anchor)
+    }
+    pub fn grain(&self) -> Option<Vector2> {
+        self.grain_angle.map(|angle| Vector2 {
+            x: angle.to_radians().sin(),
+            y: angle.to_radians().cos(),
+        })
+    }
+}
+
 pub enum Quadstate {
     On,
     Maybe,
@@ -38,9 +109,9 @@ impl Line {
     }
     pub fn in_slice(&self, point: Vector2, threshold: f32) -> bool {
         if self.p1.x - self.p2.x > 0.0 {
-            return point.x < self.p1.x + threshold && point.x > self.p2.x - threshold;
+            point.x < self.p1.x + threshold && point.x > self.p2.x - threshold
         } else {
-            return point.x < self.p2.x + threshold && point.x > self.p1.x - threshold;
+            point.x < self.p2.x + threshold && point.x > self.p1.x - threshold
         }
     }
     pub fn get_intersect_on_x(&self, point: Vector2) -> Option<f32> {
@@ -58,27 +129,14 @@ impl Line {
         }
         .normalized()
             * threshold;
-        return {
-            let p2 = self.p2 + normal;
-            let p1 = self.p1 + normal;
-
-            ((p2.x - p1.x) * (point.y - p1.y) - (point.x - p1.x) * (p2.y - p1.y)) < 0.0
-        } && {
-            let p2 = self.p2 - normal;
-            let p1 = self.p1 - normal;
-
-            ((p2.x - p1.x) * (point.y - p1.y) - (point.x - p1.x) * (p2.y - p1.y)) > 0.0
-        } && {
-            let p2 = self.p2 + normal;
-            let p1 = self.p2 - normal;
-
-            ((p2.x - p1.x) * (point.y - p1.y) - (point.x - p1.x) * (p2.y - p1.y)) > 0.0
-        } && {
-            let p2 = self.p1 + normal;
-            let p1 = self.p1 - normal;
-
-            ((p2.x - p1.x) * (point.y - p1.y) - (point.x - p1.x) * (p2.y - p1.y)) < 0.0
+        // which side of the line from p1 to p2 the point is on
+        let side = |p1: Vector2, p2: Vector2| {
+            (p2.x - p1.x) * (point.y - p1.y) - (point.x - p1.x) * (p2.y - p1.y)
         };
+        side(self.p1 + normal, self.p2 + normal) < 0.0
+            && side(self.p1 - normal, self.p2 - normal) > 0.0
+            && side(self.p2 - normal, self.p2 + normal) > 0.0
+            && side(self.p1 - normal, self.p1 + normal) < 0.0
     }
     // same shape
     pub fn partial_match(&self, other: &Line) -> bool {
@@ -86,31 +144,178 @@ impl Line {
     }
 }
 
+#[derive(Debug)]
+pub enum DraftError {
+    Io(io::Error),
+    Xml(xml::reader::Error),
+    Attribute { name: String, value: String },
+    NoGeometry,
+    Tolerance(f32),
+}
+
+impl fmt::Display for DraftError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DraftError::Io(err) => write!(f, "Could not access the file: {}", err),
+            DraftError::Xml(err) => write!(f, "The file is not valid SVG: {}", err),
+            DraftError::Attribute { name, value } => {
+                write!(f, "Attribute {}=\"{}\" is not a number", name, value)
+            }
+            DraftError::NoGeometry => write!(f, "The file has no shapes that can be simulated"),
+            DraftError::Tolerance(tolerance) => {
+                write!(
+                    f,
+                    "The curve tolerance has to be above 0, not {}",
+                    tolerance
+                )
+            }
+        }
+    }
+}
+
+impl Error for DraftError {}
+
+impl From<io::Error> for DraftError {
+    fn from(err: io::Error) -> Self {
+        DraftError::Io(err)
+    }
+}
+
+impl From<xml::reader::Error> for DraftError {
+    fn from(err: xml::reader::Error) -> Self {
+        DraftError::Xml(err)
+    }
+}
+
+fn parse_attr(name: &str, value: &str) -> Result<f32, DraftError> {
+    value
+        .trim()
+        .parse::<f32>()
+        .map_err(|_| DraftError::Attribute {
+            name: name.to_string(),
+            value: value.to_string(),
+        })
+}
+
+// missing attributes fall back to 0 like the svg spec says
+fn attr_f32(attrs: &HashMap<String, String>, name: &str) -> Result<f32, DraftError> {
+    match attrs.get(name) {
+        None => Ok(0.0),
+        Some(value) => parse_attr(name, value),
+    }
+}
+
+fn attr_or(attrs: &HashMap<String, String>, name: &str, default: f32) -> Result<f32, DraftError> {
+    match attrs.get(name) {
+        Some(value) => parse_attr(name, value),
+        None => Ok(default),
+    }
+}
+
+// rx and ry may be left out or set to auto
+fn attr_radius(attrs: &HashMap<String, String>, name: &str) -> Result<Option<f32>, DraftError> {
+    match attrs.get(name).map(|value| value.trim()) {
+        None | Some("auto") => Ok(None),
+        Some(value) => Ok(Some(parse_attr(name, value)?)),
+    }
+}
+
+// Reads a weaverling:piece element written by save, a material without numbers for some of its
+// fields gets the defaults for those like presets do
+fn read_piece(attrs: &HashMap<String, String>) -> Result<Piece, DraftError> {
+    let mut piece = Piece::new(Vector2 {
+        x: attr_f32(attrs, "x")?,
+        y: attr_f32(attrs, "y")?,
+    });
+    if let Some(thickness) = attrs.get("thickness") {
+        piece.thickness = Some(parse_attr("thickness", thickness)?);
+    }
+    if let Some(layer) = attrs.get("layer") {
+        piece.layer = layer
+            .trim()
+            .parse::<i32>()
+            .map_err(|_| DraftError::Attribute {
+                name: "layer".to_string(),
+                value: layer.to_string(),
+            })?;
+    }
+    if let Some(angle) = attrs.get("grain-angle") {
+        piece.grain_angle = Some(parse_attr("grain-angle", angle)?);
+    }
+    if let Some(name) = attrs.get("material") {
+        let defaults = Material::default();
+        piece.material = Some(Preset {
+            name: name.to_string(),
+            material: Material {
+                density: attr_or(attrs, "density", defaults.density)?,
+                strength: attr_or(attrs, "strength", defaults.strength)?,
+                weft_strength: attr_or(attrs, "weft-strength", defaults.weft_strength)?,
+                shear_strength: attr_or(attrs, "shear-strength", defaults.shear_strength)?,
+                bending: attr_or(attrs, "bending", defaults.bending)?,
+                damping: attr_or(attrs, "damping", defaults.damping)?,
+                friction: attr_or(attrs, "friction", defaults.friction)?,
+            },
+        });
+    }
+    Ok(piece)
+}
+
+#[cfg(feature = "gui")]
 fn rect_colision(root: Vector2, size: Vector2, point: Vector2) -> bool {
     let biggest_y = root.y.max(root.y + size.y);
     let smallest_y = root.y.min(root.y + size.y);
     let biggest_x = root.x.max(root.x + size.x);
     let smallest_x = root.x.min(root.x + size.x);
-    return point.x < biggest_x
-        && point.x > smallest_x
-        && point.y < biggest_y
-        && point.y > smallest_y;
+    point.x < biggest_x && point.x > smallest_x && point.y < biggest_y && point.y > smallest_y
+}
+
+// Where the drafting view is looking, laid out like raylib's Camera2D
+#[derive(Clone, Copy)]
+pub struct DraftCamera {
+    pub offset: Vector2,
+    pub target: Vector2,
+    pub rotation: f32,
+    pub zoom: f32,
+}
+
+#[cfg(feature = "gui")]
+impl From<DraftCamera> for Camera2D {
+    fn from(camera: DraftCamera) -> Self {
+        Camera2D {
+            offset: camera.offset.into(),
+            target: camera.target.into(),
+            rotation: camera.rotation,
+            zoom: camera.zoom,
+        }
+    }
 }
 
 pub struct Draft {
     pub lines: Vec<Line>,
-    pub camera: Camera2D,
+    pub grainlines: Vec<Grainline>,
+    pub pieces: Vec<Piece>,
+    pub camera: DraftCamera,
     pub current_link: u32,
     pub first_down: Vector2,
     pub width: i32,
     pub height: i32,
+    // one drawing unit is unit_size of unit in the real world, plain svgs use pixels
+    pub unit: svg::Unit,
+    pub unit_size: f32,
+    // clicking picks whole pieces instead of lines
+    pub select_pieces: bool,
+    pub selected_piece: Option<usize>,
+    // the lines around the selected piece, kept so they dont get worked out every frame
+    selected_outline: Vec<usize>,
 }
 
 impl Draft {
-    pub fn new(file: String, width: i32, height: i32) -> Draft {
-        let mut draft = Draft {
+    pub fn empty(width: i32, height: i32) -> Draft {
+        Draft {
             lines: vec![],
-            camera: Camera2D {
+            grainlines: vec![],
+            pieces: vec![],
+            camera: DraftCamera {
                 offset: Vector2 {
                     x: (width / 2) as f32,
                     y: (height / 2) as f32,
@@ -123,57 +328,364 @@ impl Draft {
             first_down: Vector2::zero(),
             width,
             height,
-        };
+            unit: svg::Unit::Pixel,
+            unit_size: 1.0,
+            select_pieces: false,
+            selected_piece: None,
+            selected_outline: vec![],
+        }
+    }
 
-        let file = File::open(file).unwrap();
-        let file = BufReader::new(file);
-        let parser = EventReader::new(file);
-        let mut depth = 0;
-        let mut line_id = 0;
+    // For tests, an svg written inline with the default tolerance
+    #[cfg(test)]
+    pub(crate) fn from_svg(svg: &str) -> Draft {
+        Draft::read(svg.as_bytes(), 0, 0, DEFAULT_TOLERANCE).unwrap()
+    }
+
+    // Tolerance is how many millimeters a flattened curve may stray from the real one
+    pub fn new(file: String, width: i32, height: i32, tolerance: f32) -> Result<Draft, DraftError> {
+        let file = File::open(file)?;
+        Draft::read(BufReader::new(file), width, height, tolerance)
+    }
+
+    // Same as new but the svg can come from anywhere
+    pub fn read(
+        svg: impl Read,
+        width: i32,
+        height: i32,
+        tolerance: f32,
+    ) -> Result<Draft, DraftError> {
+        if !(tolerance.is_finite() && tolerance > 0.0) {
+            return Err(DraftError::Tolerance(tolerance));
+        }
+        let mut draft = Draft::empty(width, height);
+        let centimeters = tolerance / 10.0;
+        // in drawing units, which are only known once the root element is read
+        let mut unit_tolerance = draft.centimeters_to_units(centimeters);
+
+        let parser = EventReader::new(svg);
+        // one entry per open element, each already combined with its parents
+        let mut transforms: Vec<svg::Transform> = vec![];
         for e in parser {
-            line_id += 1;
-            match e {
-                Ok(XmlEvent::StartElement {
+            match e? {
+                XmlEvent::StartElement {
                     name, attributes, ..
-                }) => {
+                } => {
+                    let ours = name.namespace.as_deref() == Some(NAMESPACE);
                     let local_name = name.local_name;
 
-                    let mut p1 = Vector2 { x: 0.0, y: 0.0 };
-                    let mut p2 = Vector2 { x: 0.0, y: 0.0 };
-                    let is_line = local_name == "line";
-
+                    let mut attrs: HashMap<String, String> = HashMap::new();
+                    let mut annotations: HashMap<String, String> = HashMap::new();
                     for attr in attributes {
-                        let attr_name = attr.name;
-                        let attr_value = attr.value;
-                        match attr_name.local_name.as_str() {
-                            "x1" => p1.x = attr_value.parse::<f32>().unwrap(),
-                            "y1" => p1.y = attr_value.parse::<f32>().unwrap(),
-                            "x2" => p2.x = attr_value.parse::<f32>().unwrap(),
-                            "y2" => p2.y = attr_value.parse::<f32>().unwrap(),
-                            _ => {}
+                        if attr.name.namespace.as_deref() == Some(NAMESPACE) {
+                            annotations.insert(attr.name.local_name, attr.value);
+                        } else {
+                            attrs.insert(attr.name.local_name, attr.value);
+                        }
+                    }
+
+                    let parent = *transforms.last().unwrap_or(&svg::Transform::identity());
+                    let transform = match attrs.get("transform") {
+                        Some(data) => parent.then(&svg::parse_transform(data)),
+                        None => parent,
+                    };
+                    // flatten in local space so the error stays within tolerance once transformed
+                    let tolerance = if transform.scale_factor() > 0.0 {
+                        unit_tolerance / transform.scale_factor()
+                    } else {
+                        unit_tolerance
+                    };
+
+                    if ours {
+                        if local_name == "piece" {
+                            let mut piece = read_piece(&attrs)?;
+                            piece.anchor = transform.apply(piece.anchor);
+                            draft.pieces.push(piece);
                         }
+                        transforms.push(transform);
+                        continue;
                     }
 
-                    if is_line {
-                        draft.lines.push(Line {
-                            p1,
-                            p2,
-                            pinned: false,
-                            rigid: false,
-                            link: None,
-                            highlighted: false,
-                            line_id,
-                        });
+                    let mut polylines: Vec<svg::Polyline> = vec![];
+                    match local_name.as_str() {
+                        "svg" if transforms.is_empty() => {
+                            draft.read_units(&attrs);
+                            unit_tolerance = draft.centimeters_to_units(centimeters);
+                        }
+                        "line" => {
+                            polylines.push(svg::Polyline {
+                                points: vec![
+                                    Vector2 {
+                                        x: attr_f32(&attrs, "x1")?,
+                                        y: attr_f32(&attrs, "y1")?,
+                                    },
+                                    Vector2 {
+                                        x: attr_f32(&attrs, "x2")?,
+                                        y: attr_f32(&attrs, "y2")?,
+                                    },
+                                ],
+                                closed: false,
+                            });
+                        }
+                        "path" => {
+                            if let Some(data) = attrs.get("d") {
+                                polylines = svg::parse_path(data, tolerance);
+                            }
+                        }
+                        "polyline" | "polygon" => {
+                            if let Some(data) = attrs.get("points") {
+                                polylines.push(svg::parse_points(data, local_name == "polygon"));
+                            }
+                        }
+                        "rect" => {
+                            polylines.extend(svg::rect(
+                                Vector2 {
+                                    x: attr_f32(&attrs, "x")?,
+                                    y: attr_f32(&attrs, "y")?,
+                                },
+                                Vector2 {
+                                    x: attr_f32(&attrs, "width")?,
+                                    y: attr_f32(&attrs, "height")?,
+                                },
+                                (attr_radius(&attrs, "rx")?, attr_radius(&attrs, "ry")?),
+                                tolerance,
+                            ));
+                        }
+                        "circle" => {
+                            let r = attr_f32(&attrs, "r")?;
+                            polylines.extend(svg::ellipse(
+                                Vector2 {
+                                    x: attr_f32(&attrs, "cx")?,
+                                    y: attr_f32(&attrs, "cy")?,
+                                },
+                                Vector2 { x: r, y: r },
+                                tolerance,
+                            ));
+                        }
+                        "ellipse" => {
+                            let rx = attr_radius(&attrs, "rx")?;
+                            let ry = attr_radius(&attrs, "ry")?;
+                            polylines.extend(svg::ellipse(
+                                Vector2 {
+                                    x: attr_f32(&attrs, "cx")?,
+                                    y: attr_f32(&attrs, "cy")?,
+                                },
+                                // an auto radius copies the other one
+                                Vector2 {
+                                    x: rx.or(ry).unwrap_or(0.0),
+                                    y: ry.or(rx).unwrap_or(0.0),
+                                },
+                                tolerance,
+                            ));
+                        }
+                        _ => {}
                     }
-                    depth += 1;
+
+                    if annotations
+                        .get("grainline")
+                        .is_some_and(|value| value == "true")
+                    {
+                        for polyline in polylines {
+                            for (p1, p2) in polyline.segments() {
+                                draft.grainlines.push(Grainline {
+                                    p1: transform.apply(p1),
+                                    p2: transform.apply(p2),
+                                });
+                            }
+                        }
+                        transforms.push(transform);
+                        continue;
+                    }
+
+                    let first_line = draft.lines.len();
+                    for polyline in polylines {
+                        for (p1, p2) in polyline.segments() {
+                            draft.push_line(transform.apply(p1), transform.apply(p2));
+                        }
+                    }
+                    draft.annotate(first_line, &annotations)?;
+                    transforms.push(transform);
                 }
-                Ok(XmlEvent::EndElement { name }) => {
-                    depth -= 1;
+                XmlEvent::EndElement { .. } => {
+                    transforms.pop();
                 }
                 _ => {}
             }
         }
-        return draft;
+        if draft.lines.is_empty() {
+            return Err(DraftError::NoGeometry);
+        }
+        Ok(draft)
+    }
+
+    // The root viewBox says how many drawing units the width and height span, which together
+    // with their units gives the real world size of one drawing unit
+    fn read_units(&mut self, attrs: &HashMap<String, String>) {
+        let Some(view_box) = attrs.get("viewBox") else {
+            return;
+        };
+        let view_box = svg::parse_points(view_box, false);
+        if view_box.points.len() != 2 {
+            return;
+        }
+        let view_size = view_box.points[1];
+        let width = attrs
+            .get("width")
+            .and_then(|width| svg::parse_length(width))
+            .filter(|_| view_size.x > 0.0)
+            .map(|(width, unit)| (width / view_size.x, unit));
+        let height = attrs
+            .get("height")
+            .and_then(|height| svg::parse_length(height))
+            .filter(|_| view_size.y > 0.0)
+            .map(|(height, unit)| (height / view_size.y, unit));
+        if let Some((unit_size, unit)) = width.or(height) {
+            self.unit_size = unit_size;
+            self.unit = unit;
+        }
+    }
+
+    pub fn centimeters_per_unit(&self) -> f32 {
+        self.unit_size * self.unit.centimeters()
+    }
+
+    // converts a real world length into drawing units
+    pub fn centimeters_to_units(&self, length: f32) -> f32 {
+        length / self.centimeters_per_unit()
+    }
+
+    // Applies pin, rigid and link attributes written by save to every line from one element
+    fn annotate(
+        &mut self,
+        first_line: usize,
+        annotations: &HashMap<String, String>,
+    ) -> Result<(), DraftError> {
+        let pinned = annotations
+            .get("pinned")
+            .is_some_and(|value| value == "true");
+        let rigid = annotations
+            .get("rigid")
+            .is_some_and(|value| value == "true");
+        let link = match annotations.get("link") {
+            None => None,
+            Some(value) => {
+                Some(
+                    value
+                        .trim()
+                        .parse::<u32>()
+                        .map_err(|_| DraftError::Attribute {
+                            name: "link".to_string(),
+                            value: value.to_string(),
+                        })?,
+                )
+            }
+        };
+        for line in &mut self.lines[first_line..] {
+            line.pinned = pinned;
+            line.rigid = rigid;
+            line.link = link;
+        }
+        Ok(())
+    }
+
+    // Writes every line out as a plain svg line, annotations go in their own namespace so
+    // other editors keep them around. This is lossy, curves come back as their flattened lines
+    // and transforms and units are already baked into the points
+    pub fn save(&self, file: String) -> Result<(), DraftError> {
+        let (min_bound, max_bound) = self.get_bounds();
+        let size = max_bound - min_bound;
+        let mut out = BufWriter::new(File::create(file)?);
+
+        writeln!(out, "<?xml version=\"1.0\"?>")?;
+        writeln!(
+            out,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" xmlns:{}=\"{}\" width=\"{}{}\" height=\"{}{}\" viewBox=\"{} {} {} {}\">",
+            PREFIX,
+            NAMESPACE,
+            size.x * self.unit_size,
+            self.unit.suffix(),
+            size.y * self.unit_size,
+            self.unit.suffix(),
+            min_bound.x,
+            min_bound.y,
+            size.x,
+            size.y
+        )?;
+        writeln!(
+            out,
+            "    <g fill=\"none\" stroke=\"black\" stroke-width=\"1\">"
+        )?;
+        for line in &self.lines {
+            write!(
+                out,
+                "        <line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\"",
+                line.p1.x, line.p1.y, line.p2.x, line.p2.y
+            )?;
+            if line.pinned {
+                write!(out, " {}:pinned=\"true\"", PREFIX)?;
+            }
+            if line.rigid {
+                write!(out, " {}:rigid=\"true\"", PREFIX)?;
+            }
+            if let Some(link) = line.link {
+                write!(out, " {}:link=\"{}\"", PREFIX, link)?;
+            }
+            writeln!(out, "/>")?;
+        }
+        for grainline in &self.grainlines {
+            writeln!(
+                out,
+                "        <line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" {}:grainline=\"true\"/>",
+                grainline.p1.x, grainline.p1.y, grainline.p2.x, grainline.p2.y, PREFIX
+            )?;
+        }
+        for piece in self.pieces.iter().filter(|piece| !piece.is_default()) {
+            write!(
+                out,
+                "        <{}:piece x=\"{}\" y=\"{}\" layer=\"{}\"",
+                PREFIX, piece.anchor.x, piece.anchor.y, piece.layer
+            )?;
+            if let Some(thickness) = piece.thickness {
+                write!(out, " thickness=\"{}\"", thickness)?;
+            }
+            if let Some(angle) = piece.grain_angle {
+                write!(out, " grain-angle=\"{}\"", angle)?;
+            }
+            if let Some(preset) = &piece.material {
+                let material = &preset.material;
+                write!(
+                    out,
+                    " material=\"{}\" density=\"{}\" strength=\"{}\" weft-strength=\"{}\" shear-strength=\"{}\" bending=\"{}\" damping=\"{}\" friction=\"{}\"",
+                    escape_str_attribute(&preset.name),
+                    material.density,
+                    material.strength,
+                    material.weft_strength,
+                    material.shear_strength,
+                    material.bending,
+                    material.damping,
+                    material.friction
+                )?;
+            }
+            writeln!(out, "/>")?;
+        }
+        writeln!(out, "    </g>")?;
+        writeln!(out, "</svg>")?;
+        out.flush()?;
+        Ok(())
+    }
+
+    pub fn push_line(&mut self, p1: Vector2, p2: Vector2) {
+        // ids start at 1, cloth uses 0 for segments that dont sit on a line
+        let line_id = self.lines.len() + 1;
+        self.lines.push(Line {
+            p1,
+            p2,
+            pinned: false,
+            rigid: false,
+            link: None,
+            highlighted: false,
+            line_id,
+        });
     }
 
     pub fn get_bounds(&self) -> (Vector2, Vector2) {
@@ -191,30 +703,182 @@ impl Draft {
             max_num.x = max_num.x.max(line.p1.x.max(line.p2.x));
             max_num.y = max_num.y.max(line.p1.y.max(line.p2.y));
         }
-        return (
+        (
             min_num - Vector2 { x: 1.0, y: 1.0 },
             max_num + Vector2 { x: 1.0, y: 1.0 },
-        );
+        )
+    }
+
+    // Every line joined end to end with the one at index that isnt seen yet, a closed outline
+    // if the design is drawn properly. Marks them all as seen
+    fn connected_lines(&self, index: usize, seen: &mut [bool]) -> Vec<usize> {
+        seen[index] = true;
+        let mut found: Vec<usize> = vec![index];
+        let mut stack: Vec<usize> = vec![index];
+        while let Some(current) = stack.pop() {
+            for (other, line) in self.lines.iter().enumerate() {
+                if !seen[other] && line.partial_match(&self.lines[current]) {
+                    seen[other] = true;
+                    found.push(other);
+                    stack.push(other);
+                }
+            }
+        }
+        found.sort();
+        found
+    }
+
+    // The design split into its separate outlines, every line is in exactly one
+    fn outlines(&self) -> Vec<Vec<usize>> {
+        let mut seen: Vec<bool> = vec![false; self.lines.len()];
+        let mut outlines: Vec<Vec<usize>> = vec![];
+        for index in 0..self.lines.len() {
+            if !seen[index] {
+                outlines.push(self.connected_lines(index, &mut seen));
+            }
+        }
+        outlines
+    }
+
+    // How many of lines a ray straight down from point crosses, counted the same way cloth
+    // rasterizes so both agree on what is inside
+    fn crossings(&self, lines: &[usize], point: Vector2) -> usize {
+        lines
+            .iter()
+            .filter(|&&index| {
+                let line = &self.lines[index];
+                line.in_slice(point, 0.0)
+                    && line
+                        .get_intersect_on_x(point)
+                        .is_some_and(|intersection| intersection > point.y)
+            })
+            .count()
+    }
+
+    // The lines of the outline around point, the innermost one when pieces sit inside each
+    // other. None when point isnt on any fabric, like inside a hole
+    pub fn outline_at(&self, point: Vector2) -> Option<Vec<usize>> {
+        let mut outlines = self.outlines();
+        let index = self.outline_index(&outlines, point)?;
+        Some(outlines.swap_remove(index))
+    }
+
+    // Same as outline_at but gives which of outlines it is
+    fn outline_index(&self, outlines: &[Vec<usize>], point: Vector2) -> Option<usize> {
+        let all: Vec<usize> = (0..self.lines.len()).collect();
+        if self.crossings(&all, point).is_multiple_of(2) {
+            return None;
+        }
+        let mut best: Option<(f32, usize)> = None;
+        for (index, outline) in outlines.iter().enumerate() {
+            if self.crossings(outline, point).is_multiple_of(2) {
+                continue;
+            }
+            let mut min = Vector2 {
+                x: f32::INFINITY,
+                y: f32::INFINITY,
+            };
+            let mut max = Vector2 {
+                x: -f32::INFINITY,
+                y: -f32::INFINITY,
+            };
+            for &line in outline {
+                for p in [self.lines[line].p1, self.lines[line].p2] {
+                    min = Vector2 {
+                        x: min.x.min(p.x),
+                        y: min.y.min(p.y),
+                    };
+                    max = Vector2 {
+                        x: max.x.max(p.x),
+                        y: max.y.max(p.y),
+                    };
+                }
+            }
+            let area = (max.x - min.x) * (max.y - min.y);
+            if best.is_none_or(|(best_area, _)| area < best_area) {
+                best = Some((area, index));
+            }
+        }
+        best.map(|(_, index)| index)
+    }
+
+    // Selects the piece around point, making an entry for it if nothing was set on it yet
+    pub fn select_piece_at(&mut self, point: Vector2) {
+        self.selected_piece = None;
+        self.selected_outline = vec![];
+        // worked out once for the click rather than again for every piece
+        let mut outlines = self.outlines();
+        let Some(outline) = self.outline_index(&outlines, point) else {
+            return;
+        };
+        let existing = self
+            .pieces
+            .iter()
+            .position(|piece| self.outline_index(&outlines, piece.anchor) == Some(outline));
+        self.selected_piece = Some(match existing {
+            Some(index) => index,
+            None => {
+                self.pieces.push(Piece::new(point));
+                self.pieces.len() - 1
+            }
+        });
+        self.selected_outline = outlines.swap_remove(outline);
+    }
+
+    pub fn set_select_pieces(&mut self, to: bool) {
+        self.select_pieces = to;
+        self.selected_piece = None;
+        self.selected_outline = vec![];
+        for line in &mut self.lines {
+            line.highlighted = false;
+        }
+    }
+
+    pub fn get_selected_piece(&self) -> Option<Piece> {
+        self.selected_piece.map(|index| self.pieces[index].clone())
     }
 
+    // Everything but the anchor is taken from piece, the selection stays where it was clicked
+    pub fn set_piece(&mut self, piece: Piece) {
+        let Some(index) = self.selected_piece else {
+            return;
+        };
+        let anchor = self.pieces[index].anchor;
+        self.pieces[index] = Piece { anchor, ..piece };
+    }
+}
+
+#[cfg(feature = "gui")]
+impl Draft {
     pub fn draw(&mut self, d: &mut RaylibDrawHandle) {
-        let move_camera = (d.is_key_down(raylib::ffi::KeyboardKey::KEY_LEFT_CONTROL)
-            || d.is_key_down(raylib::ffi::KeyboardKey::KEY_SPACE));
+        let move_camera = d.is_key_down(raylib::ffi::KeyboardKey::KEY_LEFT_CONTROL)
+            || d.is_key_down(raylib::ffi::KeyboardKey::KEY_SPACE);
         if d.is_mouse_button_down(raylib::ffi::MouseButton::MOUSE_BUTTON_LEFT) && move_camera {
             let mut delta = d.get_mouse_delta();
             delta.scale(-1.0 / self.camera.zoom);
-            self.camera.target += delta;
+            self.camera.target += Vector2::from(delta);
         }
         let wheel = d.get_mouse_wheel_move();
-        let mouse_world_pos = d.get_screen_to_world2D(d.get_mouse_position(), self.camera);
-        self.camera.offset = d.get_mouse_position();
+        let mouse_world_pos = Vector2::from(
+            d.get_screen_to_world2D(d.get_mouse_position(), Camera2D::from(self.camera)),
+        );
+        self.camera.offset = Vector2::from(d.get_mouse_position());
         self.camera.target = mouse_world_pos;
         let scale = 0.2 * wheel;
-        self.camera.zoom = self.camera.zoom + scale;
+        self.camera.zoom += scale;
 
-        let mut m = d.begin_mode2D(self.camera);
+        let mut m = d.begin_mode2D(Camera2D::from(self.camera));
 
-        if m.is_mouse_button_pressed(raylib::ffi::MouseButton::MOUSE_BUTTON_LEFT) && !move_camera {
+        if m.is_mouse_button_pressed(raylib::ffi::MouseButton::MOUSE_BUTTON_LEFT)
+            && !move_camera
+            && self.select_pieces
+        {
+            self.select_piece_at(mouse_world_pos);
+        }
+        // lines only get picked when pieces arent
+        let picking_lines = !move_camera && !self.select_pieces;
+
+        if m.is_mouse_button_pressed(raylib::ffi::MouseButton::MOUSE_BUTTON_LEFT) && picking_lines {
             if !m.is_key_down(raylib::ffi::KeyboardKey::KEY_LEFT_SHIFT) {
                 for line in &mut self.lines {
                     line.highlighted = false;
@@ -222,18 +886,18 @@ impl Draft {
             }
             self.first_down = mouse_world_pos;
         }
-        if m.is_mouse_button_down(raylib::ffi::MouseButton::MOUSE_BUTTON_LEFT) && !move_camera {
+        if m.is_mouse_button_down(raylib::ffi::MouseButton::MOUSE_BUTTON_LEFT) && picking_lines {
             let biggest_y = mouse_world_pos.y.max(self.first_down.y);
             let smallest_y = mouse_world_pos.y.min(self.first_down.y);
             let biggest_x = mouse_world_pos.x.max(self.first_down.x);
             let smallest_x = mouse_world_pos.x.min(self.first_down.x);
 
             m.draw_rectangle_v(
-                Vector2 {
+                RVector2 {
                     x: smallest_x,
                     y: smallest_y,
                 },
-                Vector2 {
+                RVector2 {
                     x: biggest_x - smallest_x,
                     y: biggest_y - smallest_y,
                 },
@@ -241,24 +905,28 @@ impl Draft {
             );
         }
 
-        for line in &mut self.lines {
+        for (index, line) in self.lines.iter_mut().enumerate() {
             if m.is_mouse_button_down(raylib::ffi::MouseButton::MOUSE_BUTTON_LEFT)
-                && !move_camera
+                && picking_lines
                 && (rect_colision(self.first_down, mouse_world_pos - self.first_down, line.p1)
                     || rect_colision(self.first_down, mouse_world_pos - self.first_down, line.p2))
             {
                 line.highlighted = true;
             } else if m.is_mouse_button_down(raylib::ffi::MouseButton::MOUSE_BUTTON_LEFT)
-                && !move_camera
+                && picking_lines
                 && !m.is_key_down(raylib::ffi::KeyboardKey::KEY_LEFT_SHIFT)
                 && !line.hitbox(mouse_world_pos, 7.0 * (1.0 / self.camera.zoom))
             {
                 line.highlighted = false;
             }
             m.draw_line_v(
-                line.p1,
-                line.p2,
-                if line.hitbox(mouse_world_pos, 7.0 * (1.0 / self.camera.zoom)) {
+                RVector2::from(line.p1),
+                RVector2::from(line.p2),
+                if self.selected_outline.contains(&index) {
+                    Color::PURPLE
+                } else if !self.select_pieces
+                    && line.hitbox(mouse_world_pos, 7.0 * (1.0 / self.camera.zoom))
+                {
                     Color::DARKRED
                 } else if line.highlighted {
                     Color::RED
@@ -269,6 +937,7 @@ impl Draft {
                 },
             );
             if m.is_mouse_button_pressed(raylib::ffi::MouseButton::MOUSE_BUTTON_LEFT)
+                && !self.select_pieces
                 && line.hitbox(mouse_world_pos, 7.0 * (1.0 / self.camera.zoom))
             {
                 line.highlighted = true;
@@ -286,7 +955,22 @@ impl Draft {
                 }
             }
         }
+        for grainline in &self.grainlines {
+            m.draw_line_v(
+                RVector2::from(grainline.p1),
+                RVector2::from(grainline.p2),
+                Color::BLUE,
+            );
+            m.draw_circle_v(
+                RVector2::from(grainline.p2),
+                3.0 / self.camera.zoom,
+                Color::BLUE,
+            );
+        }
     }
+}
+
+impl Draft {
     pub fn pin(&mut self, to: bool) {
         for line in &mut self.lines {
             if line.highlighted {
@@ -360,3 +1044,108 @@ impl Draft {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_transforms() {
+        // the inner scale happens first, then the outer translate
+        let draft = Draft::from_svg(
+            r#"<svg xmlns="http://www.w3.org/2000/svg">
+                <g transform="translate(10 20)">
+                    <g transform="scale(2)">
+                        <line x1="1" y1="1" x2="3" y2="1"/>
+                    </g>
+                    <line x1="0" y1="0" x2="1" y2="0"/>
+                </g>
+            </svg>"#,
+        );
+        assert_eq!(draft.lines.len(), 2);
+        assert_eq!(draft.lines[0].p1, Vector2::new(12.0, 22.0));
+        assert_eq!(draft.lines[0].p2, Vector2::new(16.0, 22.0));
+        // leaving the inner group drops its scale
+        assert_eq!(draft.lines[1].p1, Vector2::new(10.0, 20.0));
+        assert_eq!(draft.lines[1].p2, Vector2::new(11.0, 20.0));
+    }
+
+    #[test]
+    fn select_pieces() {
+        let mut draft = Draft::from_svg(
+            r#"<svg xmlns="http://www.w3.org/2000/svg">
+                <rect x="0" y="0" width="10" height="10"/>
+                <rect x="4" y="4" width="2" height="2"/>
+                <rect x="20" y="0" width="10" height="10"/>
+            </svg>"#,
+        );
+        draft.select_piece_at(Vector2::new(1.3, 2.7));
+        assert_eq!(draft.selected_piece, Some(0));
+        assert_eq!(draft.selected_outline, vec![0, 1, 2, 3]);
+        // the small square is a hole in the first one
+        draft.select_piece_at(Vector2::new(5.1, 5.2));
+        assert_eq!(draft.selected_piece, None);
+        draft.select_piece_at(Vector2::new(21.3, 2.7));
+        assert_eq!(draft.selected_piece, Some(1));
+        assert_eq!(draft.selected_outline, vec![8, 9, 10, 11]);
+        // anywhere else on the first piece finds the entry it already has
+        draft.select_piece_at(Vector2::new(8.3, 8.7));
+        assert_eq!(draft.selected_piece, Some(0));
+        assert_eq!(draft.pieces.len(), 2);
+    }
+
+    #[test]
+    fn view_box_units() {
+        let draft = Draft::from_svg(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="210mm" height="297mm" viewBox="0 0 21 29.7">
+                <line x1="0" y1="0" x2="21" y2="0"/>
+            </svg>"#,
+        );
+        assert_eq!(draft.unit, svg::Unit::Millimeter);
+        assert!((draft.unit_size - 10.0).abs() < 1e-4);
+        assert!((draft.centimeters_per_unit() - 1.0).abs() < 1e-4);
+        assert!((draft.centimeters_to_units(5.0) - 5.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn no_view_box_is_pixels() {
+        let draft = Draft::from_svg(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="210mm" height="297mm">
+                <line x1="0" y1="0" x2="21" y2="0"/>
+            </svg>"#,
+        );
+        assert_eq!(draft.unit, svg::Unit::Pixel);
+        assert_eq!(draft.unit_size, 1.0);
+    }
+
+    #[test]
+    fn broken_tolerance() {
+        for tolerance in [0.0, -0.1, f32::NAN, f32::INFINITY] {
+            let draft = Draft::read("<svg/>".as_bytes(), 0, 0, tolerance);
+            assert!(matches!(draft, Err(DraftError::Tolerance(_))));
+        }
+    }
+
+    #[test]
+    fn tolerance_in_real_units() {
+        // the same circle, once a 5cm radius and once 5mm, flattened to within half a millimeter
+        let sides = |unit: &str| {
+            let draft = Draft::from_svg(&format!(
+                r#"<svg xmlns="http://www.w3.org/2000/svg" width="20{}" height="20{}" viewBox="0 0 20 20">
+                        <circle cx="10" cy="10" r="5"/>
+                    </svg>"#,
+                unit, unit
+            ));
+            draft.lines.len()
+        };
+        let centimeters = sides("cm");
+        let millimeters = sides("mm");
+        // a chord across 2pi/n of the circle sags r(1 - cos(pi/n)) in the middle
+        let sag = |radius: f32, sides: usize| {
+            radius * (1.0 - (std::f32::consts::PI / sides as f32).cos())
+        };
+        assert!(sag(50.0, centimeters) <= DEFAULT_TOLERANCE);
+        assert!(sag(5.0, millimeters) <= DEFAULT_TOLERANCE);
+        assert!(centimeters > millimeters);
+    }
+}
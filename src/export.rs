@@ -0,0 +1,358 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::num::NonZeroU32;
+use std::path::Path;
+
+use serde_json::{Value, json};
+
+use crate::cloth::Cloth;
+use crate::math::{Vector2, Vector3};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MeshFormat {
+    Obj,
+    Ply,
+}
+
+impl MeshFormat {
+    // picked from the file extension, None when its not one we can write
+    pub fn from_path(path: &Path) -> Option<MeshFormat> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "obj" => Some(MeshFormat::Obj),
+            "ply" => Some(MeshFormat::Ply),
+            _ => None,
+        }
+    }
+}
+
+pub fn write_mesh(cloth: &Cloth, format: MeshFormat, out: &mut impl Write) -> io::Result<()> {
+    match format {
+        MeshFormat::Obj => write_obj(cloth, out),
+        MeshFormat::Ply => write_ply(cloth, out),
+    }
+}
+
+// Writes to a file with the format taken from its extension
+pub fn save_mesh(cloth: &Cloth, file: &Path) -> io::Result<()> {
+    let format = MeshFormat::from_path(file).ok_or(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("{} isnt an .obj or .ply file", file.display()),
+    ))?;
+    let mut writer = BufWriter::new(File::create(file)?);
+    write_mesh(cloth, format, &mut writer)?;
+    writer.flush()
+}
+
+// https://en.wikipedia.org/wiki/Wavefront_.obj_file
+pub fn write_obj(cloth: &Cloth, out: &mut impl Write) -> io::Result<()> {
+    writeln!(out, "# weaverling cloth")?;
+    for position in cloth.positions() {
+        writeln!(out, "v {} {} {}", position.x, position.y, position.z)?;
+    }
+    for uv in cloth.uvs() {
+        writeln!(out, "vt {} {}", uv.x, uv.y)?;
+    }
+    // obj indices start at 1, every vertex has a uv of the same index
+    for triangle in cloth.triangles() {
+        let [a, b, c] = triangle.map(|i| i + 1);
+        writeln!(out, "f {a}/{a} {b}/{b} {c}/{c}")?;
+    }
+    Ok(())
+}
+
+// https://paulbourke.net/dataformats/ply/ in ascii, uvs are named s and t like blender expects
+pub fn write_ply(cloth: &Cloth, out: &mut impl Write) -> io::Result<()> {
+    let positions = cloth.positions();
+    let uvs = cloth.uvs();
+    let triangles = cloth.triangles();
+
+    writeln!(out, "ply")?;
+    writeln!(out, "format ascii 1.0")?;
+    writeln!(out, "comment weaverling cloth")?;
+    writeln!(out, "element vertex {}", positions.len())?;
+    writeln!(out, "property float x")?;
+    writeln!(out, "property float y")?;
+    writeln!(out, "property float z")?;
+    writeln!(out, "property float s")?;
+    writeln!(out, "property float t")?;
+    writeln!(out, "element face {}", triangles.len())?;
+    writeln!(out, "property list uchar uint vertex_indices")?;
+    writeln!(out, "end_header")?;
+    for (position, uv) in positions.iter().zip(uvs.iter()) {
+        writeln!(
+            out,
+            "{} {} {} {} {}",
+            position.x, position.y, position.z, uv.x, uv.y
+        )?;
+    }
+    for [a, b, c] in triangles {
+        writeln!(out, "3 {a} {b} {c}")?;
+    }
+    Ok(())
+}
+
+// Keeps the cloth positions every few steps so the whole settle can be played back later
+pub struct Recording {
+    every: u32,
+    // seconds between frames, so playback runs at the speed the cloth moved
+    frame_time: f32,
+    steps: u32,
+    triangles: Vec<[u32; 3]>,
+    uvs: Vec<Vector2>,
+    frames: Vec<Vec<Vector3>>,
+}
+
+impl Recording {
+    // starts with the cloth as it is now, every is how many steps go by between frames
+    pub fn new(cloth: &Cloth, every: NonZeroU32) -> Self {
+        Recording {
+            every: every.get(),
+            frame_time: every.get() as f32 * cloth.timestep,
+            steps: 0,
+            triangles: cloth.triangles(),
+            uvs: cloth.uvs(),
+            frames: vec![cloth.positions()],
+        }
+    }
+
+    // call once after every Cloth::step
+    pub fn after_step(&mut self, cloth: &Cloth) {
+        self.steps += 1;
+        if self.steps.is_multiple_of(self.every) {
+            self.frames.push(cloth.positions());
+        }
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn save_glb(&self, file: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(file)?);
+        self.write_glb(&mut writer)?;
+        writer.flush()
+    }
+
+    // https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html
+    // the first frame is the mesh and every later frame is a morph target holding how far each
+    // point moved from it, the animation fades from one target to the next
+    pub fn write_glb(&self, out: &mut impl Write) -> io::Result<()> {
+        // every keyframe has a weight for every target, written sparsely or not gltf counts
+        // them all with a u32
+        let target_count = self.frames.len() - 1;
+        if self
+            .frames
+            .len()
+            .checked_mul(target_count)
+            .is_none_or(|weights| weights > u32::MAX as usize)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} frames is too many for one animation, record fewer steps or raise the steps between frames",
+                    self.frames.len()
+                ),
+            ));
+        }
+        let mut bin: Vec<u8> = vec![];
+        let mut buffer_views: Vec<Value> = vec![];
+        let mut accessors: Vec<Value> = vec![];
+        // everything is 4 bytes wide so views never need padding between them
+        let mut push_view = |bin: &mut Vec<u8>, data: Vec<u8>, target: Option<u32>| {
+            let mut view = json!({
+                "buffer": 0,
+                "byteOffset": bin.len(),
+                "byteLength": data.len(),
+            });
+            if let Some(target) = target {
+                view["target"] = json!(target);
+            }
+            bin.extend(data);
+            buffer_views.push(view);
+            buffer_views.len() - 1
+        };
+
+        let mut indices: Vec<u8> = vec![];
+        for triangle in &self.triangles {
+            for index in triangle {
+                indices.extend(index.to_le_bytes());
+            }
+        }
+        let view = push_view(&mut bin, indices, Some(ELEMENT_ARRAY_BUFFER));
+        accessors.push(json!({
+            "bufferView": view,
+            "componentType": UNSIGNED_INT,
+            "count": self.triangles.len() * 3,
+            "type": "SCALAR",
+        }));
+
+        let base = &self.frames[0];
+        let view = push_view(
+            &mut bin,
+            vec3_bytes(base.iter().copied()),
+            Some(ARRAY_BUFFER),
+        );
+        accessors.push(vec3_accessor(view, base.iter().copied()));
+
+        let mut uvs: Vec<u8> = vec![];
+        for uv in &self.uvs {
+            // gltf puts v = 0 at the top of the texture
+            uvs.extend(uv.x.to_le_bytes());
+            uvs.extend((1.0 - uv.y).to_le_bytes());
+        }
+        let view = push_view(&mut bin, uvs, Some(ARRAY_BUFFER));
+        accessors.push(json!({
+            "bufferView": view,
+            "componentType": FLOAT,
+            "count": self.uvs.len(),
+            "type": "VEC2",
+        }));
+
+        let mut targets: Vec<Value> = vec![];
+        for frame in &self.frames[1..] {
+            let moved = frame.iter().zip(base.iter()).map(|(p, b)| *p - *b);
+            let view = push_view(&mut bin, vec3_bytes(moved.clone()), Some(ARRAY_BUFFER));
+            accessors.push(vec3_accessor(view, moved));
+            targets.push(json!({ "POSITION": accessors.len() - 1 }));
+        }
+
+        let mut primitive = json!({
+            "attributes": { "POSITION": 1, "TEXCOORD_0": 2 },
+            "indices": 0,
+            "mode": TRIANGLES,
+        });
+        let mut mesh = json!({ "name": "cloth" });
+        let mut gltf = json!({
+            "asset": { "version": "2.0", "generator": "weaverling" },
+            "scene": 0,
+            "scenes": [{ "nodes": [0] }],
+            "nodes": [{ "mesh": 0, "name": "cloth" }],
+        });
+
+        if !targets.is_empty() {
+            primitive["targets"] = json!(targets);
+            mesh["weights"] = json!(vec![0.0; target_count]);
+
+            let mut times: Vec<u8> = vec![];
+            // only the one target a keyframe shows is on, the rest are left at zero by writing
+            // the weights sparsely, otherwise they grow with the square of the frames
+            let mut weight_indices: Vec<u8> = vec![];
+            let mut weights: Vec<u8> = vec![];
+            for frame in 0..self.frames.len() {
+                times.extend((frame as f32 * self.frame_time).to_le_bytes());
+                // frame 0 is the base mesh so it has every weight off
+                if frame > 0 {
+                    let index = (frame * target_count + frame - 1) as u32;
+                    weight_indices.extend(index.to_le_bytes());
+                    weights.extend(1.0f32.to_le_bytes());
+                }
+            }
+            let view = push_view(&mut bin, times, None);
+            accessors.push(json!({
+                "bufferView": view,
+                "componentType": FLOAT,
+                "count": self.frames.len(),
+                "type": "SCALAR",
+                "min": [0.0],
+                "max": [(self.frames.len() - 1) as f32 * self.frame_time],
+            }));
+            let times_accessor = accessors.len() - 1;
+            let indices_view = push_view(&mut bin, weight_indices, None);
+            let values_view = push_view(&mut bin, weights, None);
+            // with no bufferView of its own every weight starts at zero
+            accessors.push(json!({
+                "componentType": FLOAT,
+                "count": self.frames.len() * target_count,
+                "type": "SCALAR",
+                "sparse": {
+                    "count": target_count,
+                    "indices": { "bufferView": indices_view, "componentType": UNSIGNED_INT },
+                    "values": { "bufferView": values_view },
+                },
+            }));
+            gltf["animations"] = json!([{
+                "name": "settle",
+                "samplers": [{
+                    "input": times_accessor,
+                    "output": accessors.len() - 1,
+                    "interpolation": "LINEAR",
+                }],
+                "channels": [{
+                    "sampler": 0,
+                    "target": { "node": 0, "path": "weights" },
+                }],
+            }]);
+        }
+
+        mesh["primitives"] = json!([primitive]);
+        gltf["meshes"] = json!([mesh]);
+        gltf["accessors"] = json!(accessors);
+        gltf["bufferViews"] = json!(buffer_views);
+        gltf["buffers"] = json!([{ "byteLength": bin.len() }]);
+
+        let mut header = serde_json::to_vec(&gltf)?;
+        // both chunks have to end on a 4 byte boundary, json pads with spaces and bin with zeros
+        while !header.len().is_multiple_of(4) {
+            header.push(b' ');
+        }
+        while !bin.len().is_multiple_of(4) {
+            bin.push(0);
+        }
+
+        let total = 12 + 8 + header.len() + 8 + bin.len();
+        out.write_all(b"glTF")?;
+        out.write_all(&2u32.to_le_bytes())?;
+        out.write_all(&(total as u32).to_le_bytes())?;
+        out.write_all(&(header.len() as u32).to_le_bytes())?;
+        out.write_all(b"JSON")?;
+        out.write_all(&header)?;
+        out.write_all(&(bin.len() as u32).to_le_bytes())?;
+        out.write_all(b"BIN\0")?;
+        out.write_all(&bin)?;
+        Ok(())
+    }
+}
+
+// gltf enum values
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+const TRIANGLES: u32 = 4;
+
+fn vec3_bytes(points: impl Iterator<Item = Vector3>) -> Vec<u8> {
+    let mut bytes: Vec<u8> = vec![];
+    for point in points {
+        bytes.extend(point.x.to_le_bytes());
+        bytes.extend(point.y.to_le_bytes());
+        bytes.extend(point.z.to_le_bytes());
+    }
+    bytes
+}
+
+// position accessors have to list their bounds
+fn vec3_accessor(view: usize, points: impl Iterator<Item = Vector3>) -> Value {
+    let mut min = [f32::INFINITY; 3];
+    let mut max = [f32::NEG_INFINITY; 3];
+    let mut count = 0;
+    for point in points {
+        for (axis, value) in [point.x, point.y, point.z].into_iter().enumerate() {
+            min[axis] = min[axis].min(value);
+            max[axis] = max[axis].max(value);
+        }
+        count += 1;
+    }
+    if count == 0 {
+        min = [0.0; 3];
+        max = [0.0; 3];
+    }
+    json!({
+        "bufferView": view,
+        "componentType": FLOAT,
+        "count": count,
+        "type": "VEC3",
+        "min": min,
+        "max": max,
+    })
+}
@@ -0,0 +1,13 @@
+// The simulation core, none of this needs a window unless the gui feature is on
+
+pub mod cloth;
+pub mod collider;
+pub mod drafting;
+pub mod export;
+pub mod material;
+pub mod math;
+pub mod parallel;
+pub mod progress;
+pub mod project;
+pub mod spatial;
+pub mod svg;
@@ -1,24 +1,32 @@
-use std::cell::{Cell, RefCell};
+use std::cell::RefCell;
+use std::mem;
+use std::num::NonZeroU32;
+use std::path::Path;
 use std::rc::Rc;
 use std::sync::{Arc, Mutex};
 
 use crate::glib::clone;
 use async_channel::{Receiver, Sender};
-use cloth::Cloth;
-use drafting::{Draft, Quadstate};
-use raylib::prelude::*;
-mod cloth;
-mod drafting;
+use cloth_simulator::cloth::{self, Cloth, Solver, Update};
+use cloth_simulator::collider::{Collider, Shape};
+use cloth_simulator::drafting::{self, Draft, Piece, Quadstate};
+use cloth_simulator::export::{self, Recording};
+use cloth_simulator::material::{self, Preset};
+use cloth_simulator::math;
+use cloth_simulator::progress::CancelToken;
+use cloth_simulator::project::{self, Project, Settings};
 use gtk::{
-    Application, ApplicationWindow, CheckButton, FileChooserDialog, FileFilter, Label, ProgressBar,
-    Separator, SpinButton, glib,
+    Application, ApplicationWindow, CheckButton, DropDown, FileChooserDialog, FileFilter, Label,
+    MessageDialog, ProgressBar, Separator, SpinButton, StringList, glib,
 };
 use gtk::{Box, Button, Notebook, prelude::*};
+use raylib::prelude::*;
 
 const APP_ID: &str = "org.foxmoss.Weaverling";
 
 const WIDTH: i32 = 640;
 const HEIGHT: i32 = 480;
+const MAX_STEPS_PER_FRAME: u32 = 4;
 enum State {
     FilePicker,
     Drafting,
@@ -28,12 +36,304 @@ enum State {
 enum Message {
     Close,
     Back,
-    OpenFile(String),
+    // the f32 is the curve tolerance in millimeters
+    OpenFile(String, f32),
     Pin(bool, bool),
     PinState(Quadstate, Quadstate),
-    Render(f64, u32, f64, f64, f64, f64),
+    SelectPieces(bool),
+    // the piece clicked in the drafting view, None when the click missed
+    PieceState(Option<Piece>),
+    SetPiece(Piece),
+    // the u32 is how many steps go between recorded frames, 0 for no recording
+    Render(Settings, u32, CancelToken),
     Link(Option<u32>),
     RenderProgress(f64),
+    OpenFailed(String),
+    SaveFile(String),
+    SaveFailed(String),
+    OpenProject(String),
+    SaveProject(String, Settings),
+    ProjectLoaded(Settings),
+    ExportMesh(String),
+    ExportFailed(String),
+    ExportAnimation(String),
+}
+
+// The spin buttons on the settings tab
+#[derive(Clone)]
+struct SettingsWidgets {
+    detail: SpinButton,
+    tolerance: SpinButton,
+    stiffness: SpinButton,
+    gravity: SpinButton,
+    damping: SpinButton,
+    strength: SpinButton,
+    weft_strength: SpinButton,
+    shear_strength: SpinButton,
+    bending: SpinButton,
+    density: SpinButton,
+    friction: SpinButton,
+    timestep: SpinButton,
+    substeps: SpinButton,
+    solver: DropDown,
+    update: DropDown,
+    compliance: SpinButton,
+    self_collision: CheckButton,
+    thickness: SpinButton,
+    // the colliders are edited one at a time, see ColliderWidgets
+    colliders: Rc<RefCell<Vec<Collider>>>,
+    collider_names: StringList,
+}
+
+// same order as the solver drop down
+const SOLVERS: [(&str, Solver); 3] = [
+    ("Springs", Solver::Springs),
+    ("XPBD", Solver::Xpbd),
+    ("Implicit", Solver::Implicit),
+];
+
+// same order as the update drop down
+const UPDATES: [(&str, Update); 2] = [
+    ("Jacobi", Update::Jacobi),
+    ("Gauss-Seidel", Update::GaussSeidel),
+];
+
+// same order as the shape drop down, new colliders start out as these. the cloth starts 1 meter
+// up with its first corner over the middle of the world
+const COLLIDER_SHAPES: [(&str, Shape); 4] = [
+    ("Ground", Shape::Ground { height: 0.0 }),
+    (
+        "Sphere",
+        Shape::Sphere {
+            center: math::Vector3 {
+                x: 0.0,
+                y: 0.5,
+                z: 0.0,
+            },
+            radius: 0.1,
+        },
+    ),
+    (
+        "Capsule",
+        Shape::Capsule {
+            start: math::Vector3 {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            end: math::Vector3 {
+                x: 0.0,
+                y: 0.5,
+                z: 0.0,
+            },
+            radius: 0.1,
+        },
+    ),
+    (
+        "Box",
+        Shape::Box {
+            center: math::Vector3 {
+                x: 0.0,
+                y: 0.25,
+                z: 0.0,
+            },
+            size: math::Vector3 {
+                x: 0.5,
+                y: 0.5,
+                z: 0.5,
+            },
+        },
+    ),
+];
+
+// what the collider drop down calls one, numbered since there can be a few of the same shape
+fn collider_name(index: usize, collider: &Collider) -> String {
+    let shape = COLLIDER_SHAPES
+        .iter()
+        .find(|(_, shape)| mem::discriminant(shape) == mem::discriminant(&collider.shape))
+        .map_or("", |(name, _)| name);
+    format!("{}: {}", index + 1, shape)
+}
+
+// The fields of whichever collider is picked on the settings tab
+#[derive(Clone)]
+struct ColliderWidgets {
+    height: SpinButton,
+    // the start for capsules
+    center: [SpinButton; 3],
+    end: [SpinButton; 3],
+    radius: SpinButton,
+    size: [SpinButton; 3],
+    friction: SpinButton,
+    // the labels and rows of height, center, end, radius and size, each shape only shows the
+    // ones it uses
+    rows: [Vec<gtk::Widget>; 5],
+}
+
+impl ColliderWidgets {
+    // collider with its shape kept and everything else from the fields
+    fn apply(&self, collider: &Collider) -> Collider {
+        let vector = |buttons: &[SpinButton; 3]| math::Vector3 {
+            x: buttons[0].value() as f32,
+            y: buttons[1].value() as f32,
+            z: buttons[2].value() as f32,
+        };
+        let radius = self.radius.value() as f32;
+        let shape = match collider.shape {
+            Shape::Ground { .. } => Shape::Ground {
+                height: self.height.value() as f32,
+            },
+            Shape::Sphere { .. } => Shape::Sphere {
+                center: vector(&self.center),
+                radius,
+            },
+            Shape::Capsule { .. } => Shape::Capsule {
+                start: vector(&self.center),
+                end: vector(&self.end),
+                radius,
+            },
+            Shape::Box { .. } => Shape::Box {
+                center: vector(&self.center),
+                size: vector(&self.size),
+            },
+        };
+        Collider {
+            shape,
+            friction: self.friction.value() as f32,
+        }
+    }
+    fn set(&self, collider: &Collider) {
+        let set_vector = |buttons: &[SpinButton; 3], vector: math::Vector3| {
+            buttons[0].set_value(vector.x as f64);
+            buttons[1].set_value(vector.y as f64);
+            buttons[2].set_value(vector.z as f64);
+        };
+        let used = match collider.shape {
+            Shape::Ground { height } => {
+                self.height.set_value(height as f64);
+                [true, false, false, false, false]
+            }
+            Shape::Sphere { center, radius } => {
+                set_vector(&self.center, center);
+                self.radius.set_value(radius as f64);
+                [false, true, false, true, false]
+            }
+            Shape::Capsule { start, end, radius } => {
+                set_vector(&self.center, start);
+                set_vector(&self.end, end);
+                self.radius.set_value(radius as f64);
+                [false, true, true, true, false]
+            }
+            Shape::Box { center, size } => {
+                set_vector(&self.center, center);
+                set_vector(&self.size, size);
+                [false, true, false, false, true]
+            }
+        };
+        self.friction.set_value(collider.friction as f64);
+        for (row, used) in self.rows.iter().zip(used) {
+            for widget in row {
+                widget.set_visible(used);
+            }
+        }
+    }
+}
+
+// a spin button for one collider field, in meters unless range says otherwise
+fn collider_button(low: f64, high: f64) -> SpinButton {
+    let button = SpinButton::builder().margin_top(6).margin_bottom(6).build();
+    button.set_range(low, high);
+    button.set_climb_rate(0.01);
+    button.set_digits(3);
+    button.set_increments(0.01, 0.1);
+    button
+}
+
+// x, y and z side by side
+fn vector_row(buttons: &[SpinButton; 3]) -> Box {
+    let row = Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(6)
+        .build();
+    for button in buttons {
+        row.append(button);
+    }
+    row
+}
+
+impl SettingsWidgets {
+    fn get(&self) -> Settings {
+        let solver = SOLVERS[self.solver.selected() as usize].1;
+        // only xpbd goes through its links one at a time, the others always update at once
+        let update = if solver == Solver::Xpbd {
+            UPDATES[self.update.selected() as usize].1
+        } else {
+            Update::Jacobi
+        };
+        Settings {
+            detail: self.detail.value(),
+            tolerance: self.tolerance.value(),
+            stiffness: self.stiffness.value() as u32,
+            gravity: self.gravity.value(),
+            damping: self.damping.value(),
+            strength: self.strength.value(),
+            weft_strength: self.weft_strength.value(),
+            shear_strength: self.shear_strength.value(),
+            seam_strength: self.strength.value() * 25.0,
+            bending: self.bending.value(),
+            density: self.density.value(),
+            friction: self.friction.value(),
+            timestep: self.timestep.value(),
+            substeps: self.substeps.value() as u32,
+            solver,
+            update,
+            compliance: self.compliance.value(),
+            seam_compliance: self.compliance.value() / 25.0,
+            self_collision: self.self_collision.is_active(),
+            thickness: self.thickness.value(),
+            colliders: self.colliders.borrow().clone(),
+        }
+    }
+    fn set(&self, settings: &Settings) {
+        self.detail.set_value(settings.detail);
+        self.tolerance.set_value(settings.tolerance);
+        self.stiffness.set_value(settings.stiffness as f64);
+        self.gravity.set_value(settings.gravity);
+        self.damping.set_value(settings.damping);
+        self.strength.set_value(settings.strength);
+        self.weft_strength.set_value(settings.weft_strength);
+        self.shear_strength.set_value(settings.shear_strength);
+        self.bending.set_value(settings.bending);
+        self.density.set_value(settings.density);
+        self.friction.set_value(settings.friction);
+        self.timestep.set_value(settings.timestep);
+        self.substeps.set_value(settings.substeps as f64);
+        let solver = SOLVERS
+            .iter()
+            .position(|(_, solver)| *solver == settings.solver)
+            .unwrap_or(0);
+        self.solver.set_selected(solver as u32);
+        let update = UPDATES
+            .iter()
+            .position(|(_, update)| *update == settings.update)
+            .unwrap_or(0);
+        self.update.set_selected(update as u32);
+        self.compliance.set_value(settings.compliance);
+        self.self_collision.set_active(settings.self_collision);
+        self.thickness.set_value(settings.thickness);
+        *self.colliders.borrow_mut() = settings.colliders.clone();
+        // the collider drop down fills in its fields as the names change, so colliders cant
+        // still be borrowed here
+        let names: Vec<String> = settings
+            .colliders
+            .iter()
+            .enumerate()
+            .map(|(index, collider)| collider_name(index, collider))
+            .collect();
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        self.collider_names
+            .splice(0, self.collider_names.n_items(), &names);
+    }
 }
 
 fn main() {
@@ -44,6 +344,21 @@ fn main() {
     app.run();
 }
 
+fn show_error(app: &Application, title: &str, err: String) {
+    let dialog = MessageDialog::builder()
+        .message_type(gtk::MessageType::Error)
+        .buttons(gtk::ButtonsType::Ok)
+        .text(title)
+        .secondary_text(err)
+        .modal(true)
+        .build();
+    if let Some(window) = app.active_window() {
+        dialog.set_transient_for(Some(&window));
+    }
+    dialog.connect_response(|dialog, _| dialog.destroy());
+    dialog.present();
+}
+
 fn build_ui(app: &Application) {
     let (sender_for_raylib, receiver_for_gtk) = async_channel::bounded(1);
     let (sender_for_gtk, receiver_for_raylib): (Sender<Message>, Receiver<Message>) =
@@ -78,22 +393,7 @@ fn build_ui(app: &Application) {
                 .title("Weaverling")
                 .build();
 
-            let mut draft = Draft {
-                lines: vec![],
-                camera: Camera2D {
-                    offset: Vector2 {
-                        x: (WIDTH / 2) as f32,
-                        y: (HEIGHT / 2) as f32,
-                    },
-                    target: Vector2 { x: 0.0, y: 0.0 },
-                    rotation: 0.0,
-                    zoom: 5.0,
-                },
-                current_link: 1,
-                first_down: Vector2::zero(),
-                width: 10,
-                height: 10,
-            };
+            let mut draft = Draft::empty(10, 10);
 
             let mut state = State::FilePicker;
 
@@ -117,15 +417,17 @@ fn build_ui(app: &Application) {
             );
 
             let mut cloth = cloth::Cloth::generate_from_none();
+            let mut recording: Option<Recording> = None;
 
             rl.set_target_fps(30);
 
             let mut paused = false;
+            // real time that went by which the cloth hasnt caught up to yet
+            let mut unsimulated_time: f32 = 0.0;
 
             while !rl.window_should_close() {
-                let message = receiver_for_raylib.try_recv();
-                match message {
-                    Ok(message_body) => match message_body {
+                if let Ok(message_body) = receiver_for_raylib.try_recv() {
+                    match message_body {
                         Message::Close => {
                             break;
                         }
@@ -133,36 +435,104 @@ fn build_ui(app: &Application) {
                             draft.pin(pin_state);
                             draft.rigid(rigid_state);
                         }
+                        Message::SelectPieces(to) => {
+                            draft.set_select_pieces(to);
+                        }
+                        Message::PieceState(_) => {}
+                        Message::SetPiece(piece) => {
+                            draft.set_piece(piece);
+                        }
 
                         Message::Back => {
                             state = State::Drafting;
                         }
 
-                        Message::OpenFile(file) => {
-                            draft = Draft::new(file, WIDTH, HEIGHT);
-                            state = State::Drafting;
+                        Message::OpenFile(file, tolerance) => {
+                            match Draft::new(file, WIDTH, HEIGHT, tolerance) {
+                                Ok(d) => {
+                                    let select_pieces = draft.select_pieces;
+                                    draft = d;
+                                    draft.set_select_pieces(select_pieces);
+                                    state = State::Drafting;
+                                }
+                                Err(err) => {
+                                    state = State::FilePicker;
+                                    sender_for_raylib
+                                        .send_blocking(Message::OpenFailed(err.to_string()))
+                                        .expect("The channel needs to be open.");
+                                }
+                            }
+                        }
+                        Message::OpenFailed(_) => {}
+                        Message::SaveFile(file) => {
+                            if let Err(err) = draft.save(file) {
+                                sender_for_raylib
+                                    .send_blocking(Message::SaveFailed(err.to_string()))
+                                    .expect("The channel needs to be open.");
+                            }
+                        }
+                        Message::SaveFailed(_) => {}
+                        Message::OpenProject(file) => match Project::load(file) {
+                            Ok(project) => {
+                                let select_pieces = draft.select_pieces;
+                                draft = project.draft(WIDTH, HEIGHT);
+                                draft.set_select_pieces(select_pieces);
+                                cam = project.camera();
+                                state = State::Drafting;
+                                sender_for_raylib
+                                    .send_blocking(Message::ProjectLoaded(project.settings))
+                                    .expect("The channel needs to be open.");
+                            }
+                            Err(err) => {
+                                state = State::FilePicker;
+                                sender_for_raylib
+                                    .send_blocking(Message::OpenFailed(err.to_string()))
+                                    .expect("The channel needs to be open.");
+                            }
+                        },
+                        Message::SaveProject(file, settings) => {
+                            if let Err(err) =
+                                Project::new(&draft, settings).with_camera(&cam).save(file)
+                            {
+                                sender_for_raylib
+                                    .send_blocking(Message::SaveFailed(err.to_string()))
+                                    .expect("The channel needs to be open.");
+                            }
+                        }
+                        Message::ProjectLoaded(_) => {}
+                        Message::ExportMesh(file) => {
+                            if let Err(err) = export::save_mesh(&cloth, Path::new(&file)) {
+                                sender_for_raylib
+                                    .send_blocking(Message::ExportFailed(err.to_string()))
+                                    .expect("The channel needs to be open.");
+                            }
+                        }
+                        Message::ExportFailed(_) => {}
+                        Message::ExportAnimation(file) => {
+                            let saved = match &recording {
+                                Some(recording) => recording.save_glb(Path::new(&file)),
+                                None => Err(std::io::Error::other(
+                                    "Nothing was recorded, set Record Every above 0 before rendering",
+                                )),
+                            };
+                            if let Err(err) = saved {
+                                sender_for_raylib
+                                    .send_blocking(Message::ExportFailed(err.to_string()))
+                                    .expect("The channel needs to be open.");
+                            }
                         }
                         Message::PinState(_, _) => {}
-                        Message::Render(
-                            detail,
-                            stiffness,
-                            gravity,
-                            drag,
-                            strength,
-                            seam_strength,
-                        ) => {
+                        Message::Render(settings, record_every, cancel) => {
                             state = State::Rendering;
                             let cloth_res = Cloth::generate_from_draft(
                                 &draft,
-                                0.1,
-                                stiffness,
-                                detail as f32,
-                                &sender_for_raylib,
-                                &receiver_for_raylib,
-                                gravity as f32,
-                                drag as f32,
-                                strength as f32,
-                                seam_strength as f32,
+                                &settings.generate_settings(&draft),
+                                &mut |fraction: f64| {
+                                    sender_for_raylib
+                                        .send_blocking(Message::RenderProgress(fraction))
+                                        .expect("The channel needs to be open.");
+                                },
+                                &cancel,
                             );
                             match cloth_res {
                                 None => {
@@ -170,6 +540,18 @@ fn build_ui(app: &Application) {
                                 }
                                 Some(c) => {
                                     cloth = c;
+                                    cloth.timestep = settings.timestep as f32;
+                                    cloth.substeps = settings.substeps;
+                                    cloth.solver = settings.solver;
+                                    cloth.update = settings.update;
+                                    cloth.compliance = settings.compliance as f32;
+                                    cloth.seam_compliance = settings.seam_compliance as f32;
+                                    cloth.self_collision = settings.self_collision;
+                                    cloth.thickness = settings.thickness as f32 / 1000.0;
+                                    cloth.colliders = settings.colliders.clone();
+                                    unsimulated_time = 0.0;
+                                    recording = NonZeroU32::new(record_every)
+                                        .map(|every| Recording::new(&cloth, every));
                                 }
                             }
                             paused = false;
@@ -178,8 +560,7 @@ fn build_ui(app: &Application) {
                         Message::Link(l) => {
                             draft.link(l);
                         }
-                    },
-                    _ => {}
+                    }
                 }
                 let mut d = rl.begin_drawing(&thread);
                 d.clear_background(Color::WHITE);
@@ -191,10 +572,14 @@ fn build_ui(app: &Application) {
                     State::Drafting => {
                         if d.is_mouse_button_down(raylib::ffi::MouseButton::MOUSE_BUTTON_LEFT) {
                             sender_for_raylib
-                                .send_blocking(Message::PinState(
-                                    draft.get_pin_status(),
-                                    draft.get_rigid_status(),
-                                ))
+                                .send_blocking(if draft.select_pieces {
+                                    Message::PieceState(draft.get_selected_piece())
+                                } else {
+                                    Message::PinState(
+                                        draft.get_pin_status(),
+                                        draft.get_rigid_status(),
+                                    )
+                                })
                                 .expect("The channel needs to be open.");
                         }
                         draft.draw(&mut d);
@@ -223,7 +608,17 @@ fn build_ui(app: &Application) {
                         d.draw_fps(0, 0);
 
                         if !paused {
-                            cloth.step();
+                            // step in fixed chunks so the cloth moves the same at any frame
+                            // rate, slow machines drop time instead of falling further behind
+                            unsimulated_time = (unsimulated_time + d.get_frame_time())
+                                .min(cloth.timestep * MAX_STEPS_PER_FRAME as f32);
+                            while unsimulated_time >= cloth.timestep {
+                                cloth.step();
+                                if let Some(recording) = &mut recording {
+                                    recording.after_step(&cloth);
+                                }
+                                unsimulated_time -= cloth.timestep;
+                            }
                         }
                     }
                 }
@@ -272,10 +667,16 @@ fn build_ui(app: &Application) {
     done_text.set_text("Done!");
     let back_button = Button::builder().margin_top(6).margin_bottom(6).build();
     back_button.set_label("Back");
+    // swapped out every render so going back only stops the one in flight
+    let current_render = Rc::new(RefCell::new(CancelToken::new()));
+
     back_button.connect_clicked(clone!(
         #[strong]
         sender_for_gtk,
+        #[strong]
+        current_render,
         move |button| {
+            current_render.borrow().cancel();
             button.parent().unwrap().hide();
             button.parent().unwrap().prev_sibling().unwrap().show();
             sender_for_gtk
@@ -301,18 +702,241 @@ fn build_ui(app: &Application) {
         }
     ));
 
+    let export_button = Button::builder()
+        .margin_top(6)
+        .margin_bottom(6)
+        .visible(false)
+        .build();
+    export_button.set_label("Export Mesh");
+
+    let mesh_filter = FileFilter::new();
+    mesh_filter.add_suffix("obj");
+    mesh_filter.add_suffix("ply");
+
+    let export_dialog = FileChooserDialog::builder()
+        .action(gtk::FileChooserAction::Save)
+        .title("Export mesh as")
+        .filter(&mesh_filter)
+        .build();
+    export_dialog.add_button("Export", gtk::ResponseType::Accept);
+    export_dialog.set_default_response(gtk::ResponseType::Accept);
+    export_dialog.set_current_name("cloth.obj");
+
+    export_dialog.connect_response(clone!(
+        #[strong]
+        sender_for_gtk,
+        move |dialog, response_type| match response_type {
+            gtk::ResponseType::Accept => match dialog.file().and_then(|file| file.path()) {
+                None => {}
+                Some(file_path) => {
+                    let file_path = if file_path.extension().is_some() {
+                        file_path
+                    } else {
+                        file_path.with_extension("obj")
+                    };
+                    sender_for_gtk
+                        .borrow_mut()
+                        .send_blocking(Message::ExportMesh(file_path.to_string_lossy().to_string()))
+                        .expect("The channel needs to be open.");
+                    dialog.hide();
+                }
+            },
+            _ => {
+                dialog.hide();
+            }
+        }
+    ));
+
+    export_button.connect_clicked(move |_| {
+        export_dialog.present();
+    });
+
+    let export_animation_button = Button::builder()
+        .margin_top(6)
+        .margin_bottom(6)
+        .visible(false)
+        .build();
+    export_animation_button.set_label("Export Animation");
+
+    let animation_filter = FileFilter::new();
+    animation_filter.add_suffix("glb");
+
+    let export_animation_dialog = FileChooserDialog::builder()
+        .action(gtk::FileChooserAction::Save)
+        .title("Export animation as")
+        .filter(&animation_filter)
+        .build();
+    export_animation_dialog.add_button("Export", gtk::ResponseType::Accept);
+    export_animation_dialog.set_default_response(gtk::ResponseType::Accept);
+    export_animation_dialog.set_current_name("cloth.glb");
+
+    export_animation_dialog.connect_response(clone!(
+        #[strong]
+        sender_for_gtk,
+        move |dialog, response_type| match response_type {
+            gtk::ResponseType::Accept => match dialog.file().and_then(|file| file.path()) {
+                None => {}
+                Some(file_path) => {
+                    let file_path = file_path.with_extension("glb");
+                    sender_for_gtk
+                        .borrow_mut()
+                        .send_blocking(Message::ExportAnimation(
+                            file_path.to_string_lossy().to_string(),
+                        ))
+                        .expect("The channel needs to be open.");
+                    dialog.hide();
+                }
+            },
+            _ => {
+                dialog.hide();
+            }
+        }
+    ));
+
+    export_animation_button.connect_clicked(move |_| {
+        export_animation_dialog.present();
+    });
+
     render_container.append(&progress_bar);
     render_container.append(&done_text);
+    render_container.append(&export_button);
+    render_container.append(&export_animation_button);
     render_container.append(&back_button);
     render_container.append(&close_button);
 
     let continue_button = Button::builder().margin_top(6).margin_bottom(6).build();
     continue_button.set_label("Render!");
 
+    let save_button = Button::builder().margin_top(6).margin_bottom(6).build();
+    save_button.set_label("Export Design (As Lines)");
+
+    let save_project_button = Button::builder().margin_top(6).margin_bottom(6).build();
+    save_project_button.set_label("Save Project");
+
+    let save_project_as_button = Button::builder().margin_top(6).margin_bottom(6).build();
+    save_project_as_button.set_label("Save Project As");
+
+    // custom presets get added to the end of all three of these
+    let presets: Rc<RefCell<Vec<Preset>>> = Rc::new(RefCell::new(material::presets()));
+    let material_names = StringList::new(&["Custom"]);
+    let piece_material_names = StringList::new(&["Same As Settings"]);
+    for preset in presets.borrow().iter() {
+        material_names.append(&preset.name);
+        piece_material_names.append(&preset.name);
+    }
+
+    let select_pieces_button = CheckButton::builder()
+        .margin_top(6)
+        .margin_bottom(6)
+        .build();
+    select_pieces_button.set_label(Some("Select Pieces"));
+
+    let piece_material_label = Label::builder().margin_top(6).margin_bottom(6).build();
+    piece_material_label.set_label("Piece Material");
+
+    let piece_material_dropdown = DropDown::builder()
+        .model(&piece_material_names)
+        .margin_top(6)
+        .margin_bottom(6)
+        .build();
+
+    let thickness_check = CheckButton::builder()
+        .margin_top(6)
+        .margin_bottom(6)
+        .build();
+    thickness_check.set_label(Some("Own Thickness in mm (Otherwise From Settings)"));
+
+    let thickness_button = SpinButton::builder().margin_top(6).margin_bottom(6).build();
+    thickness_button.set_range(0.01, 50.0);
+    thickness_button.set_climb_rate(0.1);
+    thickness_button.set_digits(2);
+    thickness_button.set_increments(0.1, 1.0);
+    thickness_button.set_value(drafting::DEFAULT_THICKNESS as f64);
+
+    let layer_label = Label::builder().margin_top(6).margin_bottom(6).build();
+    layer_label.set_label("Layer (Higher Starts On Top)");
+
+    let layer_button = SpinButton::builder().margin_top(6).margin_bottom(6).build();
+    layer_button.set_range(-20.0, 20.0);
+    layer_button.set_increments(1.0, 5.0);
+
+    let grain_angle_check = CheckButton::builder()
+        .margin_top(6)
+        .margin_bottom(6)
+        .build();
+    grain_angle_check.set_label(Some("Grainline Angle in Degrees (0 Runs Down The Page)"));
+
+    let grain_angle_button = SpinButton::builder().margin_top(6).margin_bottom(6).build();
+    grain_angle_button.set_range(-180.0, 180.0);
+    grain_angle_button.set_climb_rate(1.0);
+    grain_angle_button.set_digits(1);
+    grain_angle_button.set_increments(1.0, 45.0);
+
+    let apply_piece_button = Button::builder().margin_top(6).margin_bottom(6).build();
+    apply_piece_button.set_label("Apply To Piece");
+
+    // hidden until a piece gets clicked
+    let piece_widgets: Vec<gtk::Widget> = vec![
+        piece_material_label.clone().upcast(),
+        piece_material_dropdown.clone().upcast(),
+        thickness_check.clone().upcast(),
+        thickness_button.clone().upcast(),
+        layer_label.clone().upcast(),
+        layer_button.clone().upcast(),
+        grain_angle_check.clone().upcast(),
+        grain_angle_button.clone().upcast(),
+        apply_piece_button.clone().upcast(),
+    ];
+    // the piece the widgets were last filled in from, so applying keeps its anchor
+    let current_piece: Rc<RefCell<Option<Piece>>> = Rc::new(RefCell::new(None));
+
+    apply_piece_button.connect_clicked(clone!(
+        #[strong]
+        sender_for_gtk,
+        #[strong]
+        current_piece,
+        #[strong]
+        presets,
+        #[strong]
+        piece_material_dropdown,
+        #[strong]
+        thickness_check,
+        #[strong]
+        thickness_button,
+        #[strong]
+        layer_button,
+        #[strong]
+        grain_angle_check,
+        #[strong]
+        grain_angle_button,
+        move |_| {
+            let Some(mut piece) = current_piece.borrow().clone() else {
+                return;
+            };
+            let selected = piece_material_dropdown.selected() as usize;
+            piece.material = match selected {
+                0 => None,
+                _ => presets.borrow().get(selected - 1).cloned(),
+            };
+            piece.thickness = thickness_check
+                .is_active()
+                .then(|| thickness_button.value() as f32);
+            piece.layer = layer_button.value() as i32;
+            piece.grain_angle = grain_angle_check
+                .is_active()
+                .then(|| grain_angle_button.value() as f32);
+            *current_piece.borrow_mut() = Some(piece.clone());
+            sender_for_gtk
+                .borrow_mut()
+                .send_blocking(Message::SetPiece(piece))
+                .expect("The channel needs to be open.");
+        }
+    ));
+
     let link_label = Label::builder().margin_top(6).margin_bottom(6).build();
     link_label.set_label("Link Number");
 
-    let current_spin_state = Rc::new(RefCell::new(0 as u32));
+    let current_spin_state = Rc::new(RefCell::new(0u32));
     let spin_button = SpinButton::builder().margin_top(6).margin_bottom(6).build();
     spin_button.set_range(0.0, 100.0);
     spin_button.set_increments(1.0, 100.0);
@@ -380,14 +1004,8 @@ fn build_ui(app: &Application) {
         move |_| {
             let pin_state = current_pin_state.borrow_mut();
             let rigid_state = current_rigid_state.borrow_mut();
-            let pin_set_val = match *pin_state {
-                Quadstate::On => true,
-                _ => false,
-            };
-            let rigid_set_val = match *rigid_state {
-                Quadstate::On => true,
-                _ => false,
-            };
+            let pin_set_val = matches!(*pin_state, Quadstate::On);
+            let rigid_set_val = matches!(*rigid_state, Quadstate::On);
 
             sender_for_gtk
                 .borrow_mut()
@@ -410,15 +1028,44 @@ fn build_ui(app: &Application) {
         }
     ));
 
+    let line_widgets: Vec<gtk::Widget> = vec![
+        pin_button.clone().upcast(),
+        rigid_button.clone().upcast(),
+        apply_button.clone().upcast(),
+        link_label.clone().upcast(),
+        spin_button.clone().upcast(),
+        link_button.clone().upcast(),
+    ];
+
+    select_pieces_button.connect_toggled(clone!(
+        #[strong]
+        sender_for_gtk,
+        #[strong]
+        current_piece,
+        #[strong]
+        piece_widgets,
+        move |select| {
+            // whatever was selected before is dropped on the raylib side too
+            for widget in line_widgets.iter().chain(piece_widgets.iter()) {
+                widget.hide();
+            }
+            *current_piece.borrow_mut() = None;
+            sender_for_gtk
+                .borrow_mut()
+                .send_blocking(Message::SelectPieces(select.is_active()))
+                .expect("The channel needs to be open.");
+        }
+    ));
+
     let detail_label = Label::builder().margin_top(6).margin_bottom(6).build();
-    detail_label.set_label("Detail (Lower == More Detail)");
+    detail_label.set_label("Detail in cm (Lower == More Detail)");
 
     let detail_button = SpinButton::builder().margin_top(6).margin_bottom(6).build();
-    detail_button.set_range(0.0, 10.0);
+    detail_button.set_range(0.0, 25.0);
     detail_button.set_climb_rate(0.1);
     detail_button.set_digits(3);
     detail_button.set_increments(0.1, 10.0);
-    detail_button.set_value(2.0);
+    detail_button.set_value(5.0);
 
     let stiffness_label = Label::builder().margin_top(6).margin_bottom(6).build();
     stiffness_label.set_label("Stiffness");
@@ -430,34 +1077,469 @@ fn build_ui(app: &Application) {
     stiffness_button.set_value(2.0);
 
     let gravity_label = Label::builder().margin_top(6).margin_bottom(6).build();
-    gravity_label.set_label("Gravity");
+    gravity_label.set_label("Gravity in m/s²");
+
+    let defaults = Settings::default();
+
+    let tolerance_label = Label::builder().margin_top(6).margin_bottom(6).build();
+    tolerance_label.set_label("Curve Tolerance in mm (Used When Opening An SVG)");
+
+    let tolerance_button = SpinButton::builder().margin_top(6).margin_bottom(6).build();
+    tolerance_button.set_range(0.01, 10.0);
+    tolerance_button.set_climb_rate(0.1);
+    tolerance_button.set_digits(2);
+    tolerance_button.set_increments(0.1, 1.0);
+    tolerance_button.set_value(defaults.tolerance);
 
     let gravity_button = SpinButton::builder().margin_top(6).margin_bottom(6).build();
-    gravity_button.set_range(-1.0, 1.0);
-    gravity_button.set_climb_rate(0.001);
-    gravity_button.set_digits(5);
-    gravity_button.set_increments(0.001, 1.0);
-    gravity_button.set_value(0.001);
-
-    let drag_label = Label::builder().margin_top(6).margin_bottom(6).build();
-    drag_label.set_label("Drag");
-
-    let drag_button = SpinButton::builder().margin_top(6).margin_bottom(6).build();
-    drag_button.set_range(0.0, 1.0);
-    drag_button.set_climb_rate(0.01);
-    drag_button.set_digits(4);
-    drag_button.set_increments(0.01, 1.0);
-    drag_button.set_value(0.9);
+    gravity_button.set_range(-100.0, 100.0);
+    gravity_button.set_climb_rate(0.1);
+    gravity_button.set_digits(3);
+    gravity_button.set_increments(0.1, 1.0);
+    gravity_button.set_value(defaults.gravity);
+
+    let damping_label = Label::builder().margin_top(6).margin_bottom(6).build();
+    damping_label.set_label("Damping (Speed Lost Per Second)");
+
+    let damping_button = SpinButton::builder().margin_top(6).margin_bottom(6).build();
+    damping_button.set_range(0.0, 100.0);
+    damping_button.set_climb_rate(0.1);
+    damping_button.set_digits(3);
+    damping_button.set_increments(0.1, 1.0);
+    damping_button.set_value(defaults.damping);
+
+    let material_label = Label::builder().margin_top(6).margin_bottom(6).build();
+    material_label.set_label("Material");
+
+    let material_dropdown = DropDown::builder()
+        .model(&material_names)
+        .margin_top(6)
+        .margin_bottom(6)
+        .build();
+
+    let load_presets_button = Button::builder().margin_top(6).margin_bottom(6).build();
+    load_presets_button.set_label("Load Presets");
+
+    let presets_filter = FileFilter::new();
+    presets_filter.add_suffix("json");
+
+    let load_presets_dialog = FileChooserDialog::builder()
+        .action(gtk::FileChooserAction::Open)
+        .title("Pick a presets file")
+        .filter(&presets_filter)
+        .build();
+    load_presets_dialog.add_button("Open", gtk::ResponseType::Accept);
+    load_presets_dialog.set_default_response(gtk::ResponseType::Accept);
+
+    load_presets_dialog.connect_response(clone!(
+        #[weak]
+        app,
+        #[strong]
+        presets,
+        #[strong]
+        material_names,
+        #[strong]
+        piece_material_names,
+        move |dialog, response_type| {
+            if response_type != gtk::ResponseType::Accept {
+                return;
+            }
+            match dialog.file().and_then(|file| file.path()) {
+                None => {}
+                Some(file_path) => {
+                    dialog.hide();
+                    match material::load_presets(file_path.to_string_lossy().to_string()) {
+                        Ok(loaded) => {
+                            for preset in &loaded {
+                                material_names.append(&preset.name);
+                                piece_material_names.append(&preset.name);
+                            }
+                            presets.borrow_mut().extend(loaded);
+                        }
+                        Err(err) => show_error(&app, "Couldn't load the presets", err.to_string()),
+                    }
+                }
+            }
+        }
+    ));
+
+    load_presets_button.connect_clicked(move |_| {
+        load_presets_dialog.present();
+    });
 
     let strength_label = Label::builder().margin_top(6).margin_bottom(6).build();
-    strength_label.set_label("Strength");
+    strength_label.set_label("Strength Along The Grain");
 
     let strength_button = SpinButton::builder().margin_top(6).margin_bottom(6).build();
-    strength_button.set_range(0.0, 1.0);
-    strength_button.set_climb_rate(0.01);
-    strength_button.set_digits(4);
-    strength_button.set_increments(0.01, 1.0);
-    strength_button.set_value(0.02);
+    strength_button.set_range(0.0, 100000.0);
+    strength_button.set_climb_rate(1.0);
+    strength_button.set_digits(1);
+    strength_button.set_increments(10.0, 100.0);
+    strength_button.set_value(defaults.strength);
+
+    let weft_strength_label = Label::builder().margin_top(6).margin_bottom(6).build();
+    weft_strength_label.set_label("Strength Across The Grain");
+
+    let weft_strength_button = SpinButton::builder().margin_top(6).margin_bottom(6).build();
+    weft_strength_button.set_range(0.0, 100000.0);
+    weft_strength_button.set_climb_rate(1.0);
+    weft_strength_button.set_digits(1);
+    weft_strength_button.set_increments(10.0, 100.0);
+    weft_strength_button.set_value(defaults.weft_strength);
+
+    let shear_strength_label = Label::builder().margin_top(6).margin_bottom(6).build();
+    shear_strength_label.set_label("Strength On The Bias");
+
+    let shear_strength_button = SpinButton::builder().margin_top(6).margin_bottom(6).build();
+    shear_strength_button.set_range(0.0, 100000.0);
+    shear_strength_button.set_climb_rate(1.0);
+    shear_strength_button.set_digits(1);
+    shear_strength_button.set_increments(10.0, 100.0);
+    shear_strength_button.set_value(defaults.shear_strength);
+
+    let bending_label = Label::builder().margin_top(6).margin_bottom(6).build();
+    bending_label.set_label("Bending Stiffness");
+
+    let bending_button = SpinButton::builder().margin_top(6).margin_bottom(6).build();
+    bending_button.set_range(0.0, 10000.0);
+    bending_button.set_climb_rate(1.0);
+    bending_button.set_digits(1);
+    bending_button.set_increments(1.0, 10.0);
+    bending_button.set_value(defaults.bending);
+
+    let density_label = Label::builder().margin_top(6).margin_bottom(6).build();
+    density_label.set_label("Density in kg/m²");
+
+    let density_button = SpinButton::builder().margin_top(6).margin_bottom(6).build();
+    density_button.set_range(0.01, 5.0);
+    density_button.set_climb_rate(0.01);
+    density_button.set_digits(3);
+    density_button.set_increments(0.01, 0.1);
+    density_button.set_value(defaults.density);
+
+    let friction_label = Label::builder().margin_top(6).margin_bottom(6).build();
+    friction_label.set_label("Friction");
+
+    let friction_button = SpinButton::builder().margin_top(6).margin_bottom(6).build();
+    friction_button.set_range(0.0, 2.0);
+    friction_button.set_climb_rate(0.01);
+    friction_button.set_digits(2);
+    friction_button.set_increments(0.05, 0.5);
+    friction_button.set_value(defaults.friction);
+
+    let timestep_label = Label::builder().margin_top(6).margin_bottom(6).build();
+    timestep_label.set_label("Timestep in Seconds");
+
+    let timestep_button = SpinButton::builder().margin_top(6).margin_bottom(6).build();
+    timestep_button.set_range(0.001, 0.1);
+    timestep_button.set_climb_rate(0.001);
+    timestep_button.set_digits(4);
+    timestep_button.set_increments(0.001, 0.01);
+    timestep_button.set_value(defaults.timestep);
+
+    let substeps_label = Label::builder().margin_top(6).margin_bottom(6).build();
+    substeps_label.set_label("Substeps (Higher == Steadier)");
+
+    let substeps_button = SpinButton::builder().margin_top(6).margin_bottom(6).build();
+    substeps_button.set_range(1.0, 64.0);
+    substeps_button.set_climb_rate(1.0);
+    substeps_button.set_increments(1.0, 4.0);
+    substeps_button.set_value(defaults.substeps as f64);
+
+    let solver_label = Label::builder().margin_top(6).margin_bottom(6).build();
+    solver_label.set_label("Solver");
+
+    let solver_names: Vec<&str> = SOLVERS.iter().map(|(name, _)| *name).collect();
+    let solver_dropdown = DropDown::from_strings(&solver_names);
+    solver_dropdown.set_margin_top(6);
+    solver_dropdown.set_margin_bottom(6);
+
+    let update_label = Label::builder().margin_top(6).margin_bottom(6).build();
+    update_label.set_label("Update (XPBD Only, Jacobi Stays Symmetric)");
+
+    let update_names: Vec<&str> = UPDATES.iter().map(|(name, _)| *name).collect();
+    let update_dropdown = DropDown::from_strings(&update_names);
+    update_dropdown.set_margin_top(6);
+    update_dropdown.set_margin_bottom(6);
+    update_dropdown.set_sensitive(SOLVERS[solver_dropdown.selected() as usize].1 == Solver::Xpbd);
+
+    solver_dropdown.connect_selected_notify(clone!(
+        #[strong]
+        update_dropdown,
+        move |dropdown| {
+            let solver = SOLVERS[dropdown.selected() as usize].1;
+            update_dropdown.set_sensitive(solver == Solver::Xpbd);
+        }
+    ));
+
+    let compliance_label = Label::builder().margin_top(6).margin_bottom(6).build();
+    compliance_label.set_label("Compliance (XPBD Only, 0 == No Stretch)");
+
+    let compliance_button = SpinButton::builder().margin_top(6).margin_bottom(6).build();
+    compliance_button.set_range(0.0, 0.01);
+    compliance_button.set_climb_rate(0.000001);
+    compliance_button.set_digits(8);
+    compliance_button.set_increments(0.000001, 0.0001);
+    compliance_button.set_value(defaults.compliance);
+
+    let self_collision_button = CheckButton::builder()
+        .margin_top(6)
+        .margin_bottom(6)
+        .build();
+    self_collision_button.set_label(Some("Self Collision (Keeps Folds And Layers Apart)"));
+    self_collision_button.set_active(defaults.self_collision);
+
+    let cloth_thickness_label = Label::builder().margin_top(6).margin_bottom(6).build();
+    cloth_thickness_label.set_label("Thickness in mm");
+
+    let cloth_thickness_button = SpinButton::builder().margin_top(6).margin_bottom(6).build();
+    cloth_thickness_button.set_range(0.01, 50.0);
+    cloth_thickness_button.set_climb_rate(0.1);
+    cloth_thickness_button.set_digits(2);
+    cloth_thickness_button.set_increments(0.1, 1.0);
+    cloth_thickness_button.set_value(defaults.thickness);
+
+    let colliders: Rc<RefCell<Vec<Collider>>> = Rc::new(RefCell::new(defaults.colliders.clone()));
+    let collider_names = StringList::new(&[]);
+
+    let colliders_label = Label::builder().margin_top(6).margin_bottom(6).build();
+    colliders_label.set_label("Colliders (What The Cloth Lands On)");
+
+    let collider_dropdown = DropDown::builder()
+        .model(&collider_names)
+        .margin_top(6)
+        .margin_bottom(6)
+        .build();
+
+    let shape_names: Vec<&str> = COLLIDER_SHAPES.iter().map(|(name, _)| *name).collect();
+    let collider_shape_dropdown = DropDown::from_strings(&shape_names);
+
+    let add_collider_button = Button::builder().build();
+    add_collider_button.set_label("Add Collider");
+
+    let add_collider_row = Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(6)
+        .margin_top(6)
+        .margin_bottom(6)
+        .build();
+    add_collider_row.append(&collider_shape_dropdown);
+    add_collider_row.append(&add_collider_button);
+
+    let remove_collider_button = Button::builder().margin_top(6).margin_bottom(6).build();
+    remove_collider_button.set_label("Remove Collider");
+
+    let collider_height_label = Label::builder().margin_top(6).margin_bottom(6).build();
+    collider_height_label.set_label("Height in m");
+    let collider_height_button = collider_button(-100.0, 100.0);
+
+    let collider_center_label = Label::builder().margin_top(6).margin_bottom(6).build();
+    collider_center_label.set_label("Center in m (X, Y, Z), The Start For Capsules");
+    let collider_center_buttons: [SpinButton; 3] =
+        std::array::from_fn(|_| collider_button(-100.0, 100.0));
+    let collider_center_row = vector_row(&collider_center_buttons);
+
+    let collider_end_label = Label::builder().margin_top(6).margin_bottom(6).build();
+    collider_end_label.set_label("End in m (X, Y, Z)");
+    let collider_end_buttons: [SpinButton; 3] =
+        std::array::from_fn(|_| collider_button(-100.0, 100.0));
+    let collider_end_row = vector_row(&collider_end_buttons);
+
+    let collider_radius_label = Label::builder().margin_top(6).margin_bottom(6).build();
+    collider_radius_label.set_label("Radius in m");
+    let collider_radius_button = collider_button(0.001, 100.0);
+
+    let collider_size_label = Label::builder().margin_top(6).margin_bottom(6).build();
+    collider_size_label.set_label("Width, Height And Depth in m");
+    let collider_size_buttons: [SpinButton; 3] =
+        std::array::from_fn(|_| collider_button(0.001, 100.0));
+    let collider_size_row = vector_row(&collider_size_buttons);
+
+    let collider_friction_label = Label::builder().margin_top(6).margin_bottom(6).build();
+    collider_friction_label.set_label("Collider Friction");
+    let collider_friction_button = collider_button(0.0, 2.0);
+
+    let apply_collider_button = Button::builder().margin_top(6).margin_bottom(6).build();
+    apply_collider_button.set_label("Apply To Collider");
+
+    let collider_widgets = ColliderWidgets {
+        height: collider_height_button.clone(),
+        center: collider_center_buttons.clone(),
+        end: collider_end_buttons.clone(),
+        radius: collider_radius_button.clone(),
+        size: collider_size_buttons.clone(),
+        friction: collider_friction_button.clone(),
+        rows: [
+            vec![
+                collider_height_label.clone().upcast(),
+                collider_height_button.clone().upcast(),
+            ],
+            vec![
+                collider_center_label.clone().upcast(),
+                collider_center_row.clone().upcast(),
+            ],
+            vec![
+                collider_end_label.clone().upcast(),
+                collider_end_row.clone().upcast(),
+            ],
+            vec![
+                collider_radius_label.clone().upcast(),
+                collider_radius_button.clone().upcast(),
+            ],
+            vec![
+                collider_size_label.clone().upcast(),
+                collider_size_row.clone().upcast(),
+            ],
+        ],
+    };
+
+    // hidden while no collider is picked, the shape rows are shown by ColliderWidgets::set
+    let collider_edit_widgets: Vec<gtk::Widget> = collider_widgets
+        .rows
+        .iter()
+        .flatten()
+        .cloned()
+        .chain([
+            remove_collider_button.clone().upcast(),
+            collider_friction_label.clone().upcast(),
+            collider_friction_button.clone().upcast(),
+            apply_collider_button.clone().upcast(),
+        ])
+        .collect();
+
+    collider_dropdown.connect_selected_notify(clone!(
+        #[strong]
+        colliders,
+        #[strong]
+        collider_widgets,
+        #[strong]
+        collider_edit_widgets,
+        move |dropdown| {
+            let picked = colliders
+                .borrow()
+                .get(dropdown.selected() as usize)
+                .copied();
+            for widget in &collider_edit_widgets {
+                widget.set_visible(picked.is_some());
+            }
+            if let Some(collider) = picked {
+                collider_widgets.set(&collider);
+            }
+        }
+    ));
+
+    add_collider_button.connect_clicked(clone!(
+        #[strong]
+        colliders,
+        #[strong]
+        collider_names,
+        #[strong]
+        collider_dropdown,
+        #[strong]
+        collider_shape_dropdown,
+        move |_| {
+            let shape = COLLIDER_SHAPES[collider_shape_dropdown.selected() as usize].1;
+            let collider = Collider::new(shape);
+            let index = colliders.borrow().len();
+            colliders.borrow_mut().push(collider);
+            collider_names.append(&collider_name(index, &collider));
+            collider_dropdown.set_selected(index as u32);
+        }
+    ));
+
+    remove_collider_button.connect_clicked(clone!(
+        #[strong]
+        colliders,
+        #[strong]
+        collider_names,
+        #[strong]
+        collider_dropdown,
+        move |_| {
+            let selected = collider_dropdown.selected() as usize;
+            if selected >= colliders.borrow().len() {
+                return;
+            }
+            colliders.borrow_mut().remove(selected);
+            // everything after it moves up a number
+            let names: Vec<String> = colliders
+                .borrow()
+                .iter()
+                .enumerate()
+                .map(|(index, collider)| collider_name(index, collider))
+                .collect();
+            let names: Vec<&str> = names.iter().map(String::as_str).collect();
+            collider_names.splice(0, collider_names.n_items(), &names);
+            if !names.is_empty() {
+                collider_dropdown.set_selected(selected.min(names.len() - 1) as u32);
+            }
+        }
+    ));
+
+    apply_collider_button.connect_clicked(clone!(
+        #[strong]
+        colliders,
+        #[strong]
+        collider_widgets,
+        #[strong]
+        collider_dropdown,
+        move |_| {
+            let selected = collider_dropdown.selected() as usize;
+            if let Some(collider) = colliders.borrow_mut().get_mut(selected) {
+                *collider = collider_widgets.apply(collider);
+            }
+        }
+    ));
+
+    let record_label = Label::builder().margin_top(6).margin_bottom(6).build();
+    record_label.set_label("Record Every N Steps (0 == Off)");
+
+    let record_button = SpinButton::builder().margin_top(6).margin_bottom(6).build();
+    record_button.set_range(0.0, 1000.0);
+    record_button.set_climb_rate(1.0);
+    record_button.set_increments(1.0, 10.0);
+    record_button.set_value(0.0);
+
+    let settings_widgets = SettingsWidgets {
+        detail: detail_button.clone(),
+        tolerance: tolerance_button.clone(),
+        stiffness: stiffness_button.clone(),
+        gravity: gravity_button.clone(),
+        damping: damping_button.clone(),
+        strength: strength_button.clone(),
+        weft_strength: weft_strength_button.clone(),
+        shear_strength: shear_strength_button.clone(),
+        bending: bending_button.clone(),
+        density: density_button.clone(),
+        friction: friction_button.clone(),
+        timestep: timestep_button.clone(),
+        substeps: substeps_button.clone(),
+        solver: solver_dropdown.clone(),
+        update: update_dropdown.clone(),
+        compliance: compliance_button.clone(),
+        self_collision: self_collision_button.clone(),
+        thickness: cloth_thickness_button.clone(),
+        colliders: colliders.clone(),
+        collider_names: collider_names.clone(),
+    };
+
+    // picking a preset only fills in the fields, Custom leaves them alone
+    material_dropdown.connect_selected_notify(clone!(
+        #[strong]
+        presets,
+        #[strong]
+        settings_widgets,
+        move |dropdown| {
+            let selected = dropdown.selected() as usize;
+            if selected == 0 {
+                return;
+            }
+            if let Some(preset) = presets.borrow().get(selected - 1) {
+                let mut settings = settings_widgets.get();
+                settings.set_material(&preset.material);
+                settings_widgets.set(&settings);
+            }
+        }
+    ));
 
     let edit_edit_container = Box::builder()
         .halign(gtk::Align::Center)
@@ -482,6 +1564,17 @@ fn build_ui(app: &Application) {
     edit_notebook.append_page(&edit_edit_container, Some(&edit_tab));
     edit_notebook.append_page(&settings_container, Some(&settings_tab));
 
+    edit_edit_container.append(&select_pieces_button);
+    edit_edit_container.append(&piece_material_label);
+    edit_edit_container.append(&piece_material_dropdown);
+    edit_edit_container.append(&thickness_check);
+    edit_edit_container.append(&thickness_button);
+    edit_edit_container.append(&layer_label);
+    edit_edit_container.append(&layer_button);
+    edit_edit_container.append(&grain_angle_check);
+    edit_edit_container.append(&grain_angle_button);
+    edit_edit_container.append(&apply_piece_button);
+
     edit_edit_container.append(&pin_button);
     edit_edit_container.append(&rigid_button);
     edit_edit_container.append(&apply_button);
@@ -497,17 +1590,67 @@ fn build_ui(app: &Application) {
 
     settings_container.append(&detail_label);
     settings_container.append(&detail_button);
+    settings_container.append(&tolerance_label);
+    settings_container.append(&tolerance_button);
     settings_container.append(&stiffness_label);
     settings_container.append(&stiffness_button);
     settings_container.append(&gravity_label);
     settings_container.append(&gravity_button);
-    settings_container.append(&drag_label);
-    settings_container.append(&drag_button);
+    settings_container.append(&material_label);
+    settings_container.append(&material_dropdown);
+    settings_container.append(&load_presets_button);
+    settings_container.append(&damping_label);
+    settings_container.append(&damping_button);
     settings_container.append(&strength_label);
     settings_container.append(&strength_button);
+    settings_container.append(&weft_strength_label);
+    settings_container.append(&weft_strength_button);
+    settings_container.append(&shear_strength_label);
+    settings_container.append(&shear_strength_button);
+    settings_container.append(&bending_label);
+    settings_container.append(&bending_button);
+    settings_container.append(&density_label);
+    settings_container.append(&density_button);
+    settings_container.append(&friction_label);
+    settings_container.append(&friction_button);
+    settings_container.append(&timestep_label);
+    settings_container.append(&timestep_button);
+    settings_container.append(&substeps_label);
+    settings_container.append(&substeps_button);
+    settings_container.append(&solver_label);
+    settings_container.append(&solver_dropdown);
+    settings_container.append(&update_label);
+    settings_container.append(&update_dropdown);
+    settings_container.append(&compliance_label);
+    settings_container.append(&compliance_button);
+    settings_container.append(&self_collision_button);
+    settings_container.append(&cloth_thickness_label);
+    settings_container.append(&cloth_thickness_button);
+    settings_container.append(&colliders_label);
+    settings_container.append(&collider_dropdown);
+    settings_container.append(&add_collider_row);
+    settings_container.append(&remove_collider_button);
+    settings_container.append(&collider_height_label);
+    settings_container.append(&collider_height_button);
+    settings_container.append(&collider_center_label);
+    settings_container.append(&collider_center_row);
+    settings_container.append(&collider_end_label);
+    settings_container.append(&collider_end_row);
+    settings_container.append(&collider_radius_label);
+    settings_container.append(&collider_radius_button);
+    settings_container.append(&collider_size_label);
+    settings_container.append(&collider_size_row);
+    settings_container.append(&collider_friction_label);
+    settings_container.append(&collider_friction_button);
+    settings_container.append(&apply_collider_button);
+    settings_container.append(&record_label);
+    settings_container.append(&record_button);
 
     edit_container.append(&edit_notebook);
     edit_container.append(&seperator);
+    edit_container.append(&save_button);
+    edit_container.append(&save_project_button);
+    edit_container.append(&save_project_as_button);
     edit_container.append(&continue_button);
 
     let upload_container = Box::builder()
@@ -528,27 +1671,181 @@ fn build_ui(app: &Application) {
     upload_dialog.add_button("Open", gtk::ResponseType::Accept);
     upload_dialog.set_default_response(gtk::ResponseType::Accept);
 
+    let open_project_button = Button::builder().margin_start(6).build();
+    open_project_button.set_label("Open Project");
+
+    let project_filter = FileFilter::new();
+    project_filter.add_suffix(project::EXTENSION);
+
+    let open_project_dialog = FileChooserDialog::builder()
+        .action(gtk::FileChooserAction::Open)
+        .title("Pick a project")
+        .filter(&project_filter)
+        .build();
+    open_project_dialog.add_button("Open", gtk::ResponseType::Accept);
+    open_project_dialog.set_default_response(gtk::ResponseType::Accept);
+
+    let save_project_dialog = FileChooserDialog::builder()
+        .action(gtk::FileChooserAction::Save)
+        .title("Save project as")
+        .filter(&project_filter)
+        .build();
+    save_project_dialog.add_button("Save", gtk::ResponseType::Accept);
+    save_project_dialog.set_default_response(gtk::ResponseType::Accept);
+    save_project_dialog.set_current_name(&format!("design.{}", project::EXTENSION));
+
     upload_container.append(&upload_button);
+    upload_container.append(&open_project_button);
+
+    // the project Save Project writes to without asking, set by opening or saving as
+    let current_project: Rc<RefCell<Option<String>>> = Rc::new(RefCell::new(None));
+
+    open_project_dialog.connect_response(clone!(
+        #[strong]
+        sender_for_gtk,
+        #[strong]
+        current_project,
+        #[strong]
+        upload_container,
+        #[strong]
+        edit_container,
+        move |dialog, response_type| match response_type {
+            gtk::ResponseType::Accept => match dialog.file().and_then(|file| file.path()) {
+                None => {}
+                Some(file_path) => {
+                    let file = file_path.to_string_lossy().to_string();
+                    *current_project.borrow_mut() = Some(file.clone());
+                    sender_for_gtk
+                        .borrow_mut()
+                        .send_blocking(Message::OpenProject(file))
+                        .expect("The channel needs to be open.");
+                    dialog.hide();
+                }
+            },
+            _ => {
+                // nothing got opened, go back to picking something
+                dialog.hide();
+                edit_container.hide();
+                upload_container.show();
+            }
+        }
+    ));
+
+    save_project_dialog.connect_response(clone!(
+        #[strong]
+        sender_for_gtk,
+        #[strong]
+        current_project,
+        #[strong]
+        settings_widgets,
+        move |dialog, response_type| match response_type {
+            gtk::ResponseType::Accept => match dialog.file().and_then(|file| file.path()) {
+                None => {}
+                Some(file_path) => {
+                    let file_path = if file_path.extension().is_some() {
+                        file_path
+                    } else {
+                        file_path.with_extension(project::EXTENSION)
+                    };
+                    let file = file_path.to_string_lossy().to_string();
+                    *current_project.borrow_mut() = Some(file.clone());
+                    sender_for_gtk
+                        .borrow_mut()
+                        .send_blocking(Message::SaveProject(file, settings_widgets.get()))
+                        .expect("The channel needs to be open.");
+                    dialog.hide();
+                }
+            },
+            _ => {
+                dialog.hide();
+            }
+        }
+    ));
+
+    save_project_button.connect_clicked(clone!(
+        #[strong]
+        sender_for_gtk,
+        #[strong]
+        current_project,
+        #[strong]
+        settings_widgets,
+        #[strong]
+        save_project_dialog,
+        move |_| match current_project.borrow().clone() {
+            Some(file) => {
+                sender_for_gtk
+                    .borrow_mut()
+                    .send_blocking(Message::SaveProject(file, settings_widgets.get()))
+                    .expect("The channel needs to be open.");
+            }
+            None => {
+                save_project_dialog.present();
+            }
+        }
+    ));
+
+    save_project_as_button.connect_clicked(move |_| {
+        save_project_dialog.present();
+    });
+
+    let save_dialog = FileChooserDialog::builder()
+        .action(gtk::FileChooserAction::Save)
+        .title("Export design as lines")
+        .filter(&filter)
+        .build();
+    save_dialog.add_button("Save", gtk::ResponseType::Accept);
+    save_dialog.set_default_response(gtk::ResponseType::Accept);
+    save_dialog.set_current_name("design.svg");
+
+    save_dialog.connect_response(clone!(
+        #[strong]
+        sender_for_gtk,
+        move |dialog, response_type| match response_type {
+            gtk::ResponseType::Accept => match dialog.file().and_then(|file| file.path()) {
+                None => {}
+                Some(file_path) => {
+                    sender_for_gtk
+                        .borrow_mut()
+                        .send_blocking(Message::SaveFile(file_path.to_string_lossy().to_string()))
+                        .expect("The channel needs to be open.");
+                    dialog.hide();
+                }
+            },
+            _ => {
+                dialog.hide();
+            }
+        }
+    ));
+
+    save_button.connect_clicked(move |_| {
+        save_dialog.present();
+    });
 
     upload_dialog.connect_response(clone!(
         #[strong]
         sender_for_gtk,
+        #[strong]
+        current_project,
+        #[strong]
+        settings_widgets,
         move |dialog, response_type| {
-            match response_type {
-                gtk::ResponseType::Accept => match dialog.file() {
+            if response_type == gtk::ResponseType::Accept {
+                match dialog.file() {
                     None => {}
                     Some(file_path) => {
+                        *current_project.borrow_mut() = None;
                         sender_for_gtk
                             .borrow_mut()
                             .send_blocking(Message::OpenFile(
                                 // this is bad. i dont gaf
                                 file_path.path().unwrap().to_str().unwrap().to_string(),
+                                settings_widgets.tolerance.value() as f32,
                             ))
                             .expect("The channel needs to be open.");
-                        dialog.destroy();
+                        // hidden not destroyed so another file can be picked if this one fails
+                        dialog.hide();
                     }
-                },
-                _ => {}
+                }
             }
         }
     ));
@@ -557,28 +1854,23 @@ fn build_ui(app: &Application) {
         #[strong]
         sender_for_gtk,
         #[strong]
-        detail_button,
-        #[strong]
-        stiffness_button,
-        #[strong]
-        gravity_button,
+        settings_widgets,
         #[strong]
-        drag_button,
+        current_render,
         #[strong]
-        strength_button,
+        record_button,
         move |button| {
             button.parent().unwrap().next_sibling().unwrap().show();
             button.parent().unwrap().hide();
 
+            let cancel = CancelToken::new();
+            *current_render.borrow_mut() = cancel.clone();
             sender_for_gtk
                 .borrow_mut()
                 .send_blocking(Message::Render(
-                    detail_button.value(),
-                    stiffness_button.value() as u32,
-                    gravity_button.value(),
-                    drag_button.value(),
-                    strength_button.value(),
-                    strength_button.value() * 25.0,
+                    settings_widgets.get(),
+                    record_button.value() as u32,
+                    cancel,
                 ))
                 .expect("The channel needs to be open.");
         }
@@ -588,11 +1880,29 @@ fn build_ui(app: &Application) {
     design.append(&edit_container);
     design.append(&render_container);
 
-    upload_button.connect_clicked(move |_| {
-        upload_dialog.present();
-        upload_container.hide();
-        edit_container.show();
-    });
+    upload_button.connect_clicked(clone!(
+        #[strong]
+        upload_container,
+        #[strong]
+        edit_container,
+        move |_| {
+            upload_dialog.present();
+            upload_container.hide();
+            edit_container.show();
+        }
+    ));
+
+    open_project_button.connect_clicked(clone!(
+        #[strong]
+        upload_container,
+        #[strong]
+        edit_container,
+        move |_| {
+            open_project_dialog.present();
+            upload_container.hide();
+            edit_container.show();
+        }
+    ));
 
     let notebook = Notebook::builder().build();
     let design_tab = Label::builder().build();
@@ -611,6 +1921,12 @@ fn build_ui(app: &Application) {
     spin_button.hide();
     link_button.hide();
     apply_button.hide();
+    for widget in &piece_widgets {
+        widget.hide();
+    }
+    for widget in &collider_edit_widgets {
+        widget.hide();
+    }
     glib::spawn_future_local(clone!(
         #[weak]
         app,
@@ -620,12 +1936,68 @@ fn build_ui(app: &Application) {
                     Message::Close => {
                         app.active_window().unwrap().close();
                     }
-                    Message::OpenFile(file) => {
+                    Message::OpenFile(_, _) => {
                         todo!()
                     }
                     Message::Pin(_, _) => {
                         todo!()
                     }
+                    Message::SaveFailed(err) => {
+                        show_error(&app, "Couldn't save the design", err);
+                    }
+                    Message::ExportFailed(err) => {
+                        show_error(&app, "Couldn't export the mesh", err);
+                    }
+                    Message::ProjectLoaded(settings) => {
+                        settings_widgets.set(&settings);
+                    }
+                    Message::OpenFailed(err) => {
+                        edit_container.hide();
+                        upload_container.show();
+                        *current_project.borrow_mut() = None;
+
+                        show_error(&app, "Couldn't open the file", err);
+                    }
+                    Message::PieceState(piece) => {
+                        for widget in &piece_widgets {
+                            widget.set_visible(piece.is_some());
+                        }
+                        // only fill the widgets in when the selection moves so edits arent lost
+                        if *current_piece.borrow() != piece {
+                            if let Some(piece) = &piece {
+                                let selected = match &piece.material {
+                                    None => 0,
+                                    Some(material) => {
+                                        let found = presets
+                                            .borrow()
+                                            .iter()
+                                            .rposition(|preset| preset == material);
+                                        // a material from the file nobody loaded yet joins the
+                                        // list so applying doesnt lose it
+                                        match found {
+                                            Some(index) => index + 1,
+                                            None => {
+                                                material_names.append(&material.name);
+                                                piece_material_names.append(&material.name);
+                                                presets.borrow_mut().push(material.clone());
+                                                presets.borrow().len()
+                                            }
+                                        }
+                                    }
+                                };
+                                piece_material_dropdown.set_selected(selected as u32);
+                                thickness_check.set_active(piece.thickness.is_some());
+                                thickness_button.set_value(
+                                    piece.thickness.unwrap_or(drafting::DEFAULT_THICKNESS) as f64,
+                                );
+                                layer_button.set_value(piece.layer as f64);
+                                grain_angle_check.set_active(piece.grain_angle.is_some());
+                                grain_angle_button
+                                    .set_value(piece.grain_angle.unwrap_or(0.0) as f64);
+                            }
+                            *current_piece.borrow_mut() = piece;
+                        }
+                    }
                     Message::PinState(pin_state, rigid_state) => {
                         pin_button.show();
                         rigid_button.show();
@@ -673,15 +2045,19 @@ fn build_ui(app: &Application) {
                         *current_rigid_state.borrow_mut() = rigid_state;
                     }
                     Message::RenderProgress(prog) => {
-                        print!("prog {}%\n", prog * 100.0);
-                        progress_bar.set_fraction(prog as f64);
+                        println!("prog {}%", prog * 100.0);
+                        progress_bar.set_fraction(prog);
                         if prog == 0.0 {
                             done_text.hide();
+                            export_button.hide();
+                            export_animation_button.hide();
                             close_button.hide();
                             progress_bar.show();
                         }
                         if prog == 1.0 {
                             done_text.show();
+                            export_button.show();
+                            export_animation_button.show();
                             close_button.show();
                             progress_bar.hide();
                         }
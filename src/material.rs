@@ -0,0 +1,166 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader};
+
+// kilograms per square meter the strengths are measured against, a fabric twice as heavy moves
+// half as much under the same pull
+pub const DEFAULT_DENSITY: f32 = 0.15;
+
+// What a pattern piece is made of, strengths are in the same units as Cloth's
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(default)]
+pub struct Material {
+    // kilograms per square meter
+    pub density: f32,
+    // pull along the warp, across it and on the bias
+    pub strength: f32,
+    pub weft_strength: f32,
+    pub shear_strength: f32,
+    pub bending: f32,
+    // share of speed lost per second
+    pub damping: f32,
+    // how much it grips whatever it lies on, 0 slides freely
+    pub friction: f32,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material {
+            density: DEFAULT_DENSITY,
+            strength: 200.0,
+            weft_strength: 200.0,
+            shear_strength: 200.0,
+            bending: 20.0,
+            damping: 3.0,
+            friction: 0.5,
+        }
+    }
+}
+
+// A material with a name to pick it by, custom ones are read from a json list of these
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Preset {
+    pub name: String,
+    #[serde(flatten)]
+    pub material: Material,
+}
+
+fn preset(name: &str, material: Material) -> Preset {
+    Preset {
+        name: name.to_string(),
+        material,
+    }
+}
+
+// The fabrics that come built in, rough numbers picked to look right at the default detail
+pub fn presets() -> Vec<Preset> {
+    vec![
+        preset(
+            "Cotton",
+            Material {
+                density: 0.15,
+                strength: 400.0,
+                weft_strength: 300.0,
+                shear_strength: 80.0,
+                bending: 20.0,
+                damping: 3.0,
+                friction: 0.5,
+            },
+        ),
+        preset(
+            "Silk",
+            Material {
+                density: 0.06,
+                strength: 250.0,
+                weft_strength: 250.0,
+                shear_strength: 40.0,
+                bending: 2.0,
+                damping: 1.5,
+                friction: 0.3,
+            },
+        ),
+        // knits stretch more across the rows than along them
+        preset(
+            "Jersey",
+            Material {
+                density: 0.18,
+                strength: 120.0,
+                weft_strength: 60.0,
+                shear_strength: 50.0,
+                bending: 8.0,
+                damping: 3.0,
+                friction: 0.6,
+            },
+        ),
+        // not woven at all, so it is the same every way
+        preset(
+            "Felt",
+            Material {
+                density: 0.35,
+                strength: 1000.0,
+                weft_strength: 1000.0,
+                shear_strength: 1000.0,
+                bending: 300.0,
+                damping: 5.0,
+                friction: 0.8,
+            },
+        ),
+        preset(
+            "Denim",
+            Material {
+                density: 0.4,
+                strength: 2000.0,
+                weft_strength: 1500.0,
+                shear_strength: 300.0,
+                bending: 200.0,
+                damping: 4.0,
+                friction: 0.7,
+            },
+        ),
+    ]
+}
+
+#[derive(Debug)]
+pub enum MaterialError {
+    Io(io::Error),
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for MaterialError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MaterialError::Io(err) => write!(f, "Could not access the file: {}", err),
+            MaterialError::Json(err) => write!(f, "The presets are damaged: {}", err),
+        }
+    }
+}
+
+impl Error for MaterialError {}
+
+impl From<io::Error> for MaterialError {
+    fn from(err: io::Error) -> Self {
+        MaterialError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for MaterialError {
+    fn from(err: serde_json::Error) -> Self {
+        MaterialError::Json(err)
+    }
+}
+
+// Reads a json list of presets, anything a preset leaves out comes from Material::default
+pub fn load_presets(file: String) -> Result<Vec<Preset>, MaterialError> {
+    Ok(serde_json::from_reader(BufReader::new(File::open(file)?))?)
+}
+
+// Later presets win so custom ones can replace the built in ones by using the same name
+pub fn find<'a>(presets: &'a [Preset], name: &str) -> Option<&'a Material> {
+    presets
+        .iter()
+        .rev()
+        .find(|preset| preset.name.eq_ignore_ascii_case(name))
+        .map(|preset| &preset.material)
+}
@@ -0,0 +1,247 @@
+use serde::{Deserialize, Serialize};
+use std::ops::{Add, AddAssign, Div, Mul, MulAssign, Neg, Sub, SubAssign};
+
+// Plain vector types so the simulation doesnt need raylib, the gui converts at the edges
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Vector2 {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Vector2 {
+    pub fn new(x: f32, y: f32) -> Self {
+        Vector2 { x, y }
+    }
+    pub fn zero() -> Self {
+        Vector2 { x: 0.0, y: 0.0 }
+    }
+    pub fn length(&self) -> f32 {
+        (self.x * self.x + self.y * self.y).sqrt()
+    }
+    pub fn normalized(&self) -> Self {
+        let length = self.length();
+        if length == 0.0 {
+            return *self;
+        }
+        *self / length
+    }
+    pub fn dot(&self, v: Vector2) -> f32 {
+        self.x * v.x + self.y * v.y
+    }
+}
+
+impl Add for Vector2 {
+    type Output = Vector2;
+    fn add(self, v: Vector2) -> Self {
+        Vector2 {
+            x: self.x + v.x,
+            y: self.y + v.y,
+        }
+    }
+}
+impl Sub for Vector2 {
+    type Output = Vector2;
+    fn sub(self, v: Vector2) -> Self {
+        Vector2 {
+            x: self.x - v.x,
+            y: self.y - v.y,
+        }
+    }
+}
+impl Mul<f32> for Vector2 {
+    type Output = Vector2;
+    fn mul(self, s: f32) -> Self {
+        Vector2 {
+            x: self.x * s,
+            y: self.y * s,
+        }
+    }
+}
+impl Div<f32> for Vector2 {
+    type Output = Vector2;
+    fn div(self, s: f32) -> Self {
+        Vector2 {
+            x: self.x / s,
+            y: self.y / s,
+        }
+    }
+}
+impl Neg for Vector2 {
+    type Output = Vector2;
+    fn neg(self) -> Self {
+        Vector2 {
+            x: -self.x,
+            y: -self.y,
+        }
+    }
+}
+impl AddAssign for Vector2 {
+    fn add_assign(&mut self, v: Vector2) {
+        *self = *self + v;
+    }
+}
+impl SubAssign for Vector2 {
+    fn sub_assign(&mut self, v: Vector2) {
+        *self = *self - v;
+    }
+}
+
+// serialized as {"x", "y", "z"} where it shows up in project files
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Vector3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Vector3 {
+    pub fn new(x: f32, y: f32, z: f32) -> Self {
+        Vector3 { x, y, z }
+    }
+    pub fn zero() -> Self {
+        Vector3 {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        }
+    }
+    pub fn length(&self) -> f32 {
+        self.dot(*self).sqrt()
+    }
+    pub fn normalized(&self) -> Self {
+        let length = self.length();
+        if length == 0.0 {
+            return *self;
+        }
+        *self / length
+    }
+    pub fn scale_by(&self, s: f32) -> Self {
+        *self * s
+    }
+    pub fn dot(&self, v: Vector3) -> f32 {
+        self.x * v.x + self.y * v.y + self.z * v.z
+    }
+    pub fn cross(&self, v: Vector3) -> Self {
+        Vector3 {
+            x: self.y * v.z - self.z * v.y,
+            y: self.z * v.x - self.x * v.z,
+            z: self.x * v.y - self.y * v.x,
+        }
+    }
+    // smallest and largest of each axis on its own
+    pub fn min(&self, v: Vector3) -> Self {
+        Vector3 {
+            x: self.x.min(v.x),
+            y: self.y.min(v.y),
+            z: self.z.min(v.z),
+        }
+    }
+    pub fn max(&self, v: Vector3) -> Self {
+        Vector3 {
+            x: self.x.max(v.x),
+            y: self.y.max(v.y),
+            z: self.z.max(v.z),
+        }
+    }
+}
+
+impl Add for Vector3 {
+    type Output = Vector3;
+    fn add(self, v: Vector3) -> Self {
+        Vector3 {
+            x: self.x + v.x,
+            y: self.y + v.y,
+            z: self.z + v.z,
+        }
+    }
+}
+impl Sub for Vector3 {
+    type Output = Vector3;
+    fn sub(self, v: Vector3) -> Self {
+        Vector3 {
+            x: self.x - v.x,
+            y: self.y - v.y,
+            z: self.z - v.z,
+        }
+    }
+}
+impl Mul<f32> for Vector3 {
+    type Output = Vector3;
+    fn mul(self, s: f32) -> Self {
+        Vector3 {
+            x: self.x * s,
+            y: self.y * s,
+            z: self.z * s,
+        }
+    }
+}
+impl Div<f32> for Vector3 {
+    type Output = Vector3;
+    fn div(self, s: f32) -> Self {
+        Vector3 {
+            x: self.x / s,
+            y: self.y / s,
+            z: self.z / s,
+        }
+    }
+}
+impl Neg for Vector3 {
+    type Output = Vector3;
+    fn neg(self) -> Self {
+        Vector3 {
+            x: -self.x,
+            y: -self.y,
+            z: -self.z,
+        }
+    }
+}
+impl AddAssign for Vector3 {
+    fn add_assign(&mut self, v: Vector3) {
+        *self = *self + v;
+    }
+}
+impl SubAssign for Vector3 {
+    fn sub_assign(&mut self, v: Vector3) {
+        *self = *self - v;
+    }
+}
+impl MulAssign<f32> for Vector3 {
+    fn mul_assign(&mut self, s: f32) {
+        *self = *self * s;
+    }
+}
+
+#[cfg(feature = "gui")]
+mod raylib_conversions {
+    use super::{Vector2, Vector3};
+
+    impl From<Vector2> for raylib::math::Vector2 {
+        fn from(v: Vector2) -> Self {
+            raylib::math::Vector2 { x: v.x, y: v.y }
+        }
+    }
+    impl From<raylib::math::Vector2> for Vector2 {
+        fn from(v: raylib::math::Vector2) -> Self {
+            Vector2 { x: v.x, y: v.y }
+        }
+    }
+    impl From<Vector3> for raylib::math::Vector3 {
+        fn from(v: Vector3) -> Self {
+            raylib::math::Vector3 {
+                x: v.x,
+                y: v.y,
+                z: v.z,
+            }
+        }
+    }
+    impl From<raylib::math::Vector3> for Vector3 {
+        fn from(v: raylib::math::Vector3) -> Self {
+            Vector3 {
+                x: v.x,
+                y: v.y,
+                z: v.z,
+            }
+        }
+    }
+}
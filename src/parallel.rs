@@ -0,0 +1,86 @@
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+// Helpers the solvers split their work through. Every item only ever writes to itself, so with
+// the parallel feature on the cloth moves exactly the same as without, just sooner
+
+// fewer items than this per thread costs more in handing out work than it saves
+#[cfg(feature = "parallel")]
+const MIN_CHUNK: usize = 256;
+
+// sums are added up in pieces this long and then the pieces in order, so the rounding doesnt
+// depend on how many threads there are
+const SUM_CHUNK: usize = 1024;
+
+// Runs work on every item, work gets the position of the item too
+pub fn for_each<T: Send>(items: &mut [T], work: impl Fn(usize, &mut T) + Sync + Send) {
+    #[cfg(feature = "parallel")]
+    items
+        .par_iter_mut()
+        .with_min_len(MIN_CHUNK)
+        .enumerate()
+        .for_each(|(index, item)| work(index, item));
+    #[cfg(not(feature = "parallel"))]
+    for (index, item) in items.iter_mut().enumerate() {
+        work(index, item);
+    }
+}
+
+// Refills out with value for 0..count
+pub fn fill<T: Send>(out: &mut Vec<T>, count: usize, value: impl Fn(usize) -> T + Sync + Send) {
+    out.clear();
+    #[cfg(feature = "parallel")]
+    (0..count)
+        .into_par_iter()
+        .with_min_len(MIN_CHUNK)
+        .map(value)
+        .collect_into_vec(out);
+    #[cfg(not(feature = "parallel"))]
+    out.extend((0..count).map(value));
+}
+
+pub fn sum(count: usize, value: impl Fn(usize) -> f32 + Sync + Send) -> f32 {
+    let chunk = |start: usize| -> f32 { (start..(start + SUM_CHUNK).min(count)).map(&value).sum() };
+    #[cfg(feature = "parallel")]
+    let chunks: Vec<f32> = (0..count)
+        .into_par_iter()
+        .step_by(SUM_CHUNK)
+        .map(chunk)
+        .collect();
+    #[cfg(not(feature = "parallel"))]
+    let chunks: Vec<f32> = (0..count).step_by(SUM_CHUNK).map(chunk).collect();
+    chunks.iter().sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // long enough to be split up between threads, with a short piece left over at the end
+    const COUNT: usize = 3 * SUM_CHUNK + 17;
+
+    #[test]
+    fn sum_matches_a_fold_in_chunks() {
+        let value = |index: usize| (index as f32 * 0.37).sin();
+        for count in [0, 1, SUM_CHUNK, COUNT] {
+            let mut total = 0.0;
+            for start in (0..count).step_by(SUM_CHUNK) {
+                let mut chunk = 0.0;
+                for index in start..(start + SUM_CHUNK).min(count) {
+                    chunk += value(index);
+                }
+                total += chunk;
+            }
+            assert_eq!(sum(count, value), total);
+        }
+    }
+
+    #[test]
+    fn fill_and_for_each_keep_their_order() {
+        let mut out = vec![7; 3];
+        fill(&mut out, COUNT, |index| index * 2);
+        assert_eq!(out, (0..COUNT).map(|index| index * 2).collect::<Vec<_>>());
+        for_each(&mut out, |index, item| *item += index);
+        assert_eq!(out, (0..COUNT).map(|index| index * 3).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,32 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Long running work like Cloth::generate_from_draft reports how far along it is through this,
+// fraction goes from 0.0 to 1.0
+pub trait Progress {
+    fn report(&mut self, fraction: f64);
+}
+
+impl<F: FnMut(f64)> Progress for F {
+    fn report(&mut self, fraction: f64) {
+        self(fraction)
+    }
+}
+
+// Cheap to clone, every clone sees the same flag so another thread can stop the work
+#[derive(Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        CancelToken::default()
+    }
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,478 @@
+#[cfg(feature = "gui")]
+use raylib::{camera::Camera3D, math::Vector3};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+
+use crate::cloth::{GenerateSettings, Solver, Update};
+use crate::collider::Collider;
+use crate::drafting::{DEFAULT_THICKNESS, DEFAULT_TOLERANCE, Draft, Grainline, Piece};
+use crate::material::{DEFAULT_DENSITY, Material, Preset};
+use crate::math::Vector2;
+use crate::svg::Unit;
+
+pub const EXTENSION: &str = "weaverling";
+const VERSION: u32 = 4;
+
+// Everything the settings tab sends along with Message::Render, see Cloth for the units.
+// Anything missing from an older file falls back to the default
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Settings {
+    pub detail: f64,
+    pub stiffness: u32,
+    pub gravity: f64,
+    pub damping: f64,
+    // along the warp, the other two are across it and on the bias
+    pub strength: f64,
+    pub weft_strength: f64,
+    pub shear_strength: f64,
+    pub seam_strength: f64,
+    pub timestep: f64,
+    pub substeps: u32,
+    pub solver: Solver,
+    pub update: Update,
+    pub compliance: f64,
+    pub seam_compliance: f64,
+    pub bending: f64,
+    // kilograms per square meter
+    pub density: f64,
+    pub friction: f64,
+    // keeps the cloth from passing through itself, see Cloth::self_collision
+    pub self_collision: bool,
+    // millimeters, for pieces without their own
+    pub thickness: f64,
+    // the floor and obstacles the cloth lands on, none by default so it falls forever
+    pub colliders: Vec<Collider>,
+    // millimeters a curve may stray from the svg once flattened into lines, only used when an
+    // svg is opened since projects keep the lines themselves
+    pub tolerance: f64,
+}
+
+// what the settings tab starts out with
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            detail: 5.0,
+            stiffness: 2,
+            gravity: 9.81,
+            damping: 3.0,
+            strength: 200.0,
+            weft_strength: 200.0,
+            shear_strength: 200.0,
+            seam_strength: 200.0 * 25.0,
+            timestep: 1.0 / 30.0,
+            substeps: 4,
+            solver: Solver::Springs,
+            update: Update::Jacobi,
+            compliance: 0.000001,
+            seam_compliance: 0.000001 / 25.0,
+            bending: 20.0,
+            density: DEFAULT_DENSITY as f64,
+            friction: 0.5,
+            self_collision: false,
+            thickness: DEFAULT_THICKNESS as f64,
+            colliders: vec![],
+            tolerance: DEFAULT_TOLERANCE as f64,
+        }
+    }
+}
+
+impl Settings {
+    // the fabric every piece is made of unless it has its own
+    pub fn material(&self) -> Material {
+        Material {
+            density: self.density as f32,
+            strength: self.strength as f32,
+            weft_strength: self.weft_strength as f32,
+            shear_strength: self.shear_strength as f32,
+            bending: self.bending as f32,
+            damping: self.damping as f32,
+            friction: self.friction as f32,
+        }
+    }
+
+    // Fills in everything a preset covers, seams stay 25 times as strong as the fabric
+    pub fn set_material(&mut self, material: &Material) {
+        self.density = material.density as f64;
+        self.strength = material.strength as f64;
+        self.weft_strength = material.weft_strength as f64;
+        self.shear_strength = material.shear_strength as f64;
+        self.seam_strength = self.strength * 25.0;
+        self.bending = material.bending as f64;
+        self.damping = material.damping as f64;
+        self.friction = material.friction as f64;
+    }
+
+    // detail comes in centimeters, the 3d world is in meters
+    pub fn generate_settings(&self, draft: &Draft) -> GenerateSettings {
+        GenerateSettings {
+            scale: self.detail as f32 / 100.0,
+            detail: draft.centimeters_to_units(self.detail as f32),
+            stiffness: self.stiffness,
+            gravity: self.gravity as f32,
+            material: self.material(),
+            seam_strength: self.seam_strength as f32,
+        }
+    }
+}
+
+// Version 1 counted everything per frame at 30 fps, these turn those numbers into the
+// per second ones that move the cloth the same way
+fn upgrade_settings_from_v1(settings: &mut Value) {
+    let frame = 1.0 / 30.0;
+    // anything missing is left out and gets the default like in any other project
+    for key in ["gravity", "strength", "seam_strength"] {
+        if let Some(value) = settings.get(key).and_then(Value::as_f64) {
+            settings[key] = json!(value / (frame * frame));
+        }
+    }
+    if let Some(drag) = settings.get("drag").and_then(Value::as_f64) {
+        // drag was the share of speed kept each frame, 0 stopped the cloth dead which is as
+        // much damping as a float holds and anything past 1 was never valid
+        let drag = drag.clamp(f64::MIN_POSITIVE, 1.0);
+        settings["damping"] = json!(-drag.ln() / frame);
+    }
+    settings["timestep"] = json!(frame);
+    settings["substeps"] = json!(1);
+}
+
+// Version 2 had one strength for every direction
+fn upgrade_settings_from_v2(settings: &mut Value) {
+    let Some(strength) = settings.get("strength").cloned() else {
+        return;
+    };
+    settings["weft_strength"] = strength.clone();
+    settings["shear_strength"] = strength;
+}
+
+// Version 3 had no bending, so it stays off rather than the cloth stiffening up
+fn upgrade_settings_from_v3(settings: &mut Value) {
+    if settings.get("bending").is_none() {
+        settings["bending"] = json!(0.0);
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ProjectLine {
+    pub p1: [f32; 2],
+    pub p2: [f32; 2],
+    pub pinned: bool,
+    pub rigid: bool,
+    pub link: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ProjectGrainline {
+    pub p1: [f32; 2],
+    pub p2: [f32; 2],
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ProjectPiece {
+    pub anchor: [f32; 2],
+    pub material: Option<Preset>,
+    #[serde(default)]
+    pub thickness: Option<f32>,
+    #[serde(default)]
+    pub layer: i32,
+    #[serde(default)]
+    pub grain_angle: Option<f32>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CameraPlacement {
+    pub position: [f32; 3],
+    pub target: [f32; 3],
+    pub up: [f32; 3],
+    pub fovy: f32,
+    // where the drafting view was looking
+    pub draft_target: [f32; 2],
+    pub draft_zoom: f32,
+}
+
+// A .weaverling file, plain json so it diffs nicely next to the svgs
+#[derive(Serialize, Deserialize)]
+pub struct Project {
+    pub version: u32,
+    pub unit: Unit,
+    pub unit_size: f32,
+    pub lines: Vec<ProjectLine>,
+    #[serde(default)]
+    pub grainlines: Vec<ProjectGrainline>,
+    #[serde(default)]
+    pub pieces: Vec<ProjectPiece>,
+    pub settings: Settings,
+    pub camera: CameraPlacement,
+}
+
+#[derive(Debug)]
+pub enum ProjectError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Version(u32),
+}
+
+impl fmt::Display for ProjectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProjectError::Io(err) => write!(f, "Could not access the file: {}", err),
+            ProjectError::Json(err) => write!(f, "The project file is damaged: {}", err),
+            ProjectError::Version(version) => write!(
+                f,
+                "The project was saved by a newer version (format {})",
+                version
+            ),
+        }
+    }
+}
+
+impl Error for ProjectError {}
+
+impl From<io::Error> for ProjectError {
+    fn from(err: io::Error) -> Self {
+        ProjectError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for ProjectError {
+    fn from(err: serde_json::Error) -> Self {
+        ProjectError::Json(err)
+    }
+}
+
+impl Project {
+    pub fn new(draft: &Draft, settings: Settings) -> Project {
+        let mut lines: Vec<ProjectLine> = vec![];
+        for line in &draft.lines {
+            lines.push(ProjectLine {
+                p1: [line.p1.x, line.p1.y],
+                p2: [line.p2.x, line.p2.y],
+                pinned: line.pinned,
+                rigid: line.rigid,
+                link: line.link,
+            });
+        }
+        let grainlines = draft
+            .grainlines
+            .iter()
+            .map(|grainline| ProjectGrainline {
+                p1: [grainline.p1.x, grainline.p1.y],
+                p2: [grainline.p2.x, grainline.p2.y],
+            })
+            .collect();
+        let pieces = draft
+            .pieces
+            .iter()
+            .filter(|piece| !piece.is_default())
+            .map(|piece| ProjectPiece {
+                anchor: [piece.anchor.x, piece.anchor.y],
+                material: piece.material.clone(),
+                thickness: piece.thickness,
+                layer: piece.layer,
+                grain_angle: piece.grain_angle,
+            })
+            .collect();
+        Project {
+            version: VERSION,
+            unit: draft.unit,
+            unit_size: draft.unit_size,
+            lines,
+            grainlines,
+            pieces,
+            settings,
+            // the spot the gui starts its 3d camera at
+            camera: CameraPlacement {
+                position: [0.0, 10.0, 10.0],
+                target: [0.0, 0.0, 0.0],
+                up: [0.0, 1.0, 0.0],
+                fovy: 45.0,
+                draft_target: [draft.camera.target.x, draft.camera.target.y],
+                draft_zoom: draft.camera.zoom,
+            },
+        }
+    }
+
+    pub fn load(file: String) -> Result<Project, ProjectError> {
+        let mut project: Value = serde_json::from_reader(BufReader::new(File::open(file)?))?;
+        let version = project.get("version").and_then(Value::as_u64).unwrap_or(0) as u32;
+        if version > VERSION {
+            return Err(ProjectError::Version(version));
+        }
+        if version == 1 {
+            upgrade_settings_from_v1(&mut project["settings"]);
+        }
+        if version < 3 {
+            upgrade_settings_from_v2(&mut project["settings"]);
+        }
+        if version < 4 {
+            upgrade_settings_from_v3(&mut project["settings"]);
+        }
+        let mut project: Project = serde_json::from_value(project)?;
+        project.version = VERSION;
+        Ok(project)
+    }
+
+    pub fn save(&self, file: String) -> Result<(), ProjectError> {
+        let mut out = BufWriter::new(File::create(file)?);
+        serde_json::to_writer_pretty(&mut out, self)?;
+        out.flush()?;
+        Ok(())
+    }
+
+    pub fn draft(&self, width: i32, height: i32) -> Draft {
+        let mut draft = Draft::empty(width, height);
+        draft.unit = self.unit;
+        draft.unit_size = self.unit_size;
+        draft.camera.target = Vector2 {
+            x: self.camera.draft_target[0],
+            y: self.camera.draft_target[1],
+        };
+        draft.camera.zoom = self.camera.draft_zoom;
+        for line in &self.lines {
+            draft.push_line(
+                Vector2 {
+                    x: line.p1[0],
+                    y: line.p1[1],
+                },
+                Vector2 {
+                    x: line.p2[0],
+                    y: line.p2[1],
+                },
+            );
+            let pushed = draft.lines.last_mut().unwrap();
+            pushed.pinned = line.pinned;
+            pushed.rigid = line.rigid;
+            pushed.link = line.link;
+        }
+        for grainline in &self.grainlines {
+            draft.grainlines.push(Grainline {
+                p1: Vector2 {
+                    x: grainline.p1[0],
+                    y: grainline.p1[1],
+                },
+                p2: Vector2 {
+                    x: grainline.p2[0],
+                    y: grainline.p2[1],
+                },
+            });
+        }
+        for piece in &self.pieces {
+            draft.pieces.push(Piece {
+                anchor: Vector2 {
+                    x: piece.anchor[0],
+                    y: piece.anchor[1],
+                },
+                material: piece.material.clone(),
+                thickness: piece.thickness,
+                layer: piece.layer,
+                grain_angle: piece.grain_angle,
+            });
+        }
+        draft
+    }
+}
+
+#[cfg(feature = "gui")]
+impl Project {
+    pub fn with_camera(mut self, camera: &Camera3D) -> Project {
+        self.camera.position = [camera.position.x, camera.position.y, camera.position.z];
+        self.camera.target = [camera.target.x, camera.target.y, camera.target.z];
+        self.camera.up = [camera.up.x, camera.up.y, camera.up.z];
+        self.camera.fovy = camera.fovy;
+        self
+    }
+
+    pub fn camera(&self) -> Camera3D {
+        let vector = |v: [f32; 3]| Vector3 {
+            x: v[0],
+            y: v[1],
+            z: v[2],
+        };
+        Camera3D::perspective(
+            vector(self.camera.position),
+            vector(self.camera.target),
+            vector(self.camera.up),
+            self.camera.fovy,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> Project {
+        let file = format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name);
+        Project::load(file).unwrap()
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-4 * b.abs().max(1.0)
+    }
+
+    #[test]
+    fn upgrades_from_v1() {
+        let project = fixture("v1.weaverling");
+        let settings = &project.settings;
+        assert_eq!(project.version, VERSION);
+        // per frame at 30 fps becomes per second
+        assert!(close(settings.gravity, 0.01 * 900.0));
+        assert!(close(settings.damping, -(0.9f64.ln()) * 30.0));
+        assert!(close(settings.strength, 0.2 * 900.0));
+        assert!(close(settings.weft_strength, 0.2 * 900.0));
+        assert!(close(settings.shear_strength, 0.2 * 900.0));
+        assert!(close(settings.seam_strength, 5.0 * 900.0));
+        assert!(close(settings.timestep, 1.0 / 30.0));
+        assert_eq!(settings.substeps, 1);
+        assert_eq!(settings.bending, 0.0);
+        assert_eq!(project.lines.len(), 1);
+    }
+
+    #[test]
+    fn upgrades_from_v2() {
+        let project = fixture("v2.weaverling");
+        let settings = &project.settings;
+        assert_eq!(project.version, VERSION);
+        assert!(close(settings.gravity, 9.81));
+        assert!(close(settings.damping, 2.5));
+        assert!(close(settings.strength, 150.0));
+        assert!(close(settings.weft_strength, 150.0));
+        assert!(close(settings.shear_strength, 150.0));
+        assert!(close(settings.seam_strength, 3750.0));
+        assert!(close(settings.timestep, 0.02));
+        assert_eq!(settings.substeps, 3);
+        assert_eq!(settings.bending, 0.0);
+    }
+
+    #[test]
+    fn upgrades_from_v3() {
+        let project = fixture("v3.weaverling");
+        let settings = &project.settings;
+        assert_eq!(project.version, VERSION);
+        assert!(close(settings.weft_strength, 120.0));
+        assert!(close(settings.shear_strength, 90.0));
+        // bending came in with version 4, older cloth keeps draping the way it did
+        assert_eq!(settings.bending, 0.0);
+        // and the curve tolerance is only missing, it falls back to the default
+        assert_eq!(settings.tolerance, DEFAULT_TOLERANCE as f64);
+    }
+
+    #[test]
+    fn drag_out_of_range() {
+        for drag in [0.0, -0.5, 1.5] {
+            let mut settings = json!({ "drag": drag });
+            upgrade_settings_from_v1(&mut settings);
+            let damping = settings["damping"].as_f64().unwrap();
+            assert!(
+                damping.is_finite() && damping >= 0.0,
+                "{} gave {}",
+                drag,
+                damping
+            );
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use crate::math::Vector3;
+
+// Finds what is close to a point without checking everything. Space is cut into cubes
+// cell_size wide and every item is filed under each cube its box touches, so looking up the
+// cube a point is in gives everything whose box might hold it
+
+// bits kept from each axis of a cube, far away cubes can end up sharing a key but that only
+// hands back a few extra items to check
+const AXIS_BITS: u32 = 21;
+// a box covering more cubes than this along an axis goes in the overflow rather than being
+// filed under millions of cubes
+const MAX_SPAN: u32 = 16;
+
+#[derive(Default)]
+pub struct SpatialHash {
+    cell_size: f32,
+    // cube key and item, sorted by key once everything is in so each cube sits together
+    entries: Vec<(u64, usize)>,
+    // items with boxes too big to file, every lookup hands them back
+    overflow: Vec<usize>,
+}
+
+impl SpatialHash {
+    // Empties it out for a new round of inserts, keeps the memory
+    pub fn clear(&mut self, cell_size: f32) {
+        self.cell_size = cell_size;
+        self.entries.clear();
+        self.overflow.clear();
+    }
+
+    pub fn insert(&mut self, min: Vector3, max: Vector3, item: usize) {
+        let min = self.cell(min);
+        let max = self.cell(max);
+        if too_wide(min, max) {
+            self.overflow.push(item);
+            return;
+        }
+        for x in min[0]..=max[0] {
+            for y in min[1]..=max[1] {
+                for z in min[2]..=max[2] {
+                    self.entries.push((key([x, y, z]), item));
+                }
+            }
+        }
+    }
+
+    // Has to be called after the last insert and before looking anything up
+    pub fn finish(&mut self) {
+        self.entries.sort_unstable();
+    }
+
+    // Everything whose box touches the cube point is in, each item once. The ones filed under
+    // the cube come lowest first, then the overflow
+    pub fn near(&self, point: Vector3) -> impl Iterator<Item = usize> + '_ {
+        self.in_cell(self.cell(point))
+            .chain(self.overflow.iter().copied())
+    }
+
+    // Everything whose box touches any cube the box from min to max touches, each item once and
+    // lowest first. Found is cleared first so one list can be reused between lookups
+    pub fn near_box(&self, min: Vector3, max: Vector3, found: &mut Vec<usize>) {
+        found.clear();
+        let min = self.cell(min);
+        let max = self.cell(max);
+        if min == max && self.overflow.is_empty() {
+            // the usual case, one cube is already in order with nothing twice
+            found.extend(self.in_cell(min));
+            return;
+        }
+        if too_wide(min, max) {
+            // going cube by cube would take longer than handing back everything
+            found.extend(self.entries.iter().map(|entry| entry.1));
+        } else {
+            for x in min[0]..=max[0] {
+                for y in min[1]..=max[1] {
+                    for z in min[2]..=max[2] {
+                        found.extend(self.in_cell([x, y, z]));
+                    }
+                }
+            }
+        }
+        found.extend(&self.overflow);
+        found.sort_unstable();
+        found.dedup();
+    }
+
+    fn in_cell(&self, cell: [i32; 3]) -> impl Iterator<Item = usize> + '_ {
+        let key = key(cell);
+        let start = self.entries.partition_point(|entry| entry.0 < key);
+        self.entries[start..]
+            .iter()
+            .take_while(move |entry| entry.0 == key)
+            .map(|entry| entry.1)
+    }
+
+    fn cell(&self, point: Vector3) -> [i32; 3] {
+        [point.x, point.y, point.z].map(|axis| (axis / self.cell_size).floor() as i32)
+    }
+}
+
+fn too_wide(min: [i32; 3], max: [i32; 3]) -> bool {
+    (0..3).any(|axis| max[axis].abs_diff(min[axis]) >= MAX_SPAN)
+}
+
+fn key(cell: [i32; 3]) -> u64 {
+    let mask = (1 << AXIS_BITS) - 1;
+    cell.iter()
+        .fold(0, |key, axis| (key << AXIS_BITS) | (*axis as u64 & mask))
+}
@@ -0,0 +1,959 @@
+use crate::math::Vector2;
+use core::f32;
+use serde::{Deserialize, Serialize};
+
+// most lines one curve gets split into, a tiny or broken tolerance would otherwise ask for more
+// than fits in memory
+const MAX_SEGMENTS: usize = 1024;
+
+// A chain of points from a single subpath, curves are already flattened
+pub struct Polyline {
+    pub points: Vec<Vector2>,
+    pub closed: bool,
+}
+
+impl Polyline {
+    pub fn segments(&self) -> Vec<(Vector2, Vector2)> {
+        let mut segments: Vec<(Vector2, Vector2)> = vec![];
+        for pair in self.points.windows(2) {
+            if pair[0] != pair[1] {
+                segments.push((pair[0], pair[1]));
+            }
+        }
+        if self.closed && self.points.len() > 2 {
+            let first = self.points[0];
+            let last = *self.points.last().unwrap();
+            if first != last {
+                segments.push((last, first));
+            }
+        }
+        segments
+    }
+}
+
+struct PathLexer<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> PathLexer<'a> {
+    fn new(data: &'a str) -> Self {
+        PathLexer {
+            data: data.as_bytes(),
+            pos: 0,
+        }
+    }
+
+    fn skip_separators(&mut self) {
+        while self.pos < self.data.len()
+            && (self.data[self.pos].is_ascii_whitespace() || self.data[self.pos] == b',')
+        {
+            self.pos += 1;
+        }
+    }
+
+    fn next_command(&mut self) -> Option<u8> {
+        self.skip_separators();
+        if self.pos < self.data.len() && self.data[self.pos].is_ascii_alphabetic() {
+            self.pos += 1;
+            return Some(self.data[self.pos - 1]);
+        }
+        None
+    }
+
+    // true when the next token is a number, used for implicit command repeats
+    fn has_number(&mut self) -> bool {
+        self.skip_separators();
+        self.pos < self.data.len()
+            && (self.data[self.pos].is_ascii_digit()
+                || self.data[self.pos] == b'-'
+                || self.data[self.pos] == b'+'
+                || self.data[self.pos] == b'.')
+    }
+
+    fn number(&mut self) -> Option<f32> {
+        self.skip_separators();
+        let start = self.pos;
+        if self.pos < self.data.len()
+            && (self.data[self.pos] == b'-' || self.data[self.pos] == b'+')
+        {
+            self.pos += 1;
+        }
+        let mut seen_dot = false;
+        while self.pos < self.data.len() {
+            let c = self.data[self.pos];
+            if c.is_ascii_digit() {
+                self.pos += 1;
+            } else if c == b'.' && !seen_dot {
+                seen_dot = true;
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+        if self.pos < self.data.len()
+            && (self.data[self.pos] == b'e' || self.data[self.pos] == b'E')
+        {
+            let mut exponent_end = self.pos + 1;
+            if exponent_end < self.data.len()
+                && (self.data[exponent_end] == b'-' || self.data[exponent_end] == b'+')
+            {
+                exponent_end += 1;
+            }
+            if exponent_end < self.data.len() && self.data[exponent_end].is_ascii_digit() {
+                self.pos = exponent_end;
+                while self.pos < self.data.len() && self.data[self.pos].is_ascii_digit() {
+                    self.pos += 1;
+                }
+            }
+        }
+        std::str::from_utf8(&self.data[start..self.pos])
+            .ok()?
+            .parse::<f32>()
+            .ok()
+    }
+
+    // arc flags are allowed to be written without separators, "a1 1 0 0110 10"
+    fn flag(&mut self) -> Option<bool> {
+        self.skip_separators();
+        if self.pos >= self.data.len() {
+            return None;
+        }
+        let flag = match self.data[self.pos] {
+            b'0' => false,
+            b'1' => true,
+            _ => return None,
+        };
+        self.pos += 1;
+        Some(flag)
+    }
+
+    fn point(&mut self) -> Option<Vector2> {
+        let x = self.number()?;
+        let y = self.number()?;
+        Some(Vector2 { x, y })
+    }
+}
+
+// how many lines a curve is cut into, count being the exact amount worked out from tolerance
+// which is NaN or infinite when tolerance is 0 or below
+fn segment_count(count: f32, min: usize) -> usize {
+    // NaN turns into 0 and infinity into usize::MAX
+    (count.ceil() as usize).clamp(min, MAX_SEGMENTS)
+}
+
+// https://en.wikipedia.org/wiki/B%C3%A9zier_curve, sample counts from Wang's formula
+fn flatten_quadratic(
+    points: &mut Vec<Vector2>,
+    p0: Vector2,
+    p1: Vector2,
+    p2: Vector2,
+    tolerance: f32,
+) {
+    let flatness = (p0 - p1 * 2.0 + p2).length();
+    let count = segment_count((0.25 * flatness / tolerance).sqrt(), 1);
+    for i in 1..=count {
+        let t = i as f32 / count as f32;
+        let mt = 1.0 - t;
+        points.push(p0 * (mt * mt) + p1 * (2.0 * mt * t) + p2 * (t * t));
+    }
+}
+
+fn flatten_cubic(
+    points: &mut Vec<Vector2>,
+    p0: Vector2,
+    p1: Vector2,
+    p2: Vector2,
+    p3: Vector2,
+    tolerance: f32,
+) {
+    let flatness = (p0 - p1 * 2.0 + p2)
+        .length()
+        .max((p1 - p2 * 2.0 + p3).length());
+    let count = segment_count((0.75 * flatness / tolerance).sqrt(), 1);
+    for i in 1..=count {
+        let t = i as f32 / count as f32;
+        let mt = 1.0 - t;
+        points.push(
+            p0 * (mt * mt * mt)
+                + p1 * (3.0 * mt * mt * t)
+                + p2 * (3.0 * mt * t * t)
+                + p3 * (t * t * t),
+        );
+    }
+}
+
+// largest angle a chord can cover on a circle before sagging more than tolerance
+fn arc_step(radius: f32, tolerance: f32) -> f32 {
+    if tolerance < radius {
+        2.0 * (1.0 - tolerance / radius).acos()
+    } else {
+        f32::consts::FRAC_PI_2
+    }
+}
+
+// https://www.w3.org/TR/SVG2/implnote.html#ArcConversionEndpointToCenter
+fn flatten_arc(
+    points: &mut Vec<Vector2>,
+    from: Vector2,
+    radius: Vector2,
+    x_rotation: f32,
+    (large_arc, sweep): (bool, bool),
+    to: Vector2,
+    tolerance: f32,
+) {
+    let mut rx = radius.x.abs();
+    let mut ry = radius.y.abs();
+    if from == to {
+        return;
+    }
+    if rx == 0.0 || ry == 0.0 {
+        points.push(to);
+        return;
+    }
+    let (sin_phi, cos_phi) = x_rotation.to_radians().sin_cos();
+    let half = (from - to) * 0.5;
+    let x1 = cos_phi * half.x + sin_phi * half.y;
+    let y1 = -sin_phi * half.x + cos_phi * half.y;
+
+    // out of range radii get scaled up until the arc fits
+    let lambda = (x1 * x1) / (rx * rx) + (y1 * y1) / (ry * ry);
+    if lambda > 1.0 {
+        rx *= lambda.sqrt();
+        ry *= lambda.sqrt();
+    }
+
+    let numerator = rx * rx * ry * ry - rx * rx * y1 * y1 - ry * ry * x1 * x1;
+    let denominator = rx * rx * y1 * y1 + ry * ry * x1 * x1;
+    let mut coef = (numerator / denominator).max(0.0).sqrt();
+    if large_arc == sweep {
+        coef = -coef;
+    }
+    let cx1 = coef * rx * y1 / ry;
+    let cy1 = -coef * ry * x1 / rx;
+    let mid = (from + to) * 0.5;
+    let center = Vector2 {
+        x: cos_phi * cx1 - sin_phi * cy1 + mid.x,
+        y: sin_phi * cx1 + cos_phi * cy1 + mid.y,
+    };
+
+    let angle = |ux: f32, uy: f32, vx: f32, vy: f32| -> f32 {
+        let sign = if ux * vy - uy * vx < 0.0 { -1.0 } else { 1.0 };
+        let dot = (ux * vx + uy * vy) / ((ux * ux + uy * uy).sqrt() * (vx * vx + vy * vy).sqrt());
+        sign * dot.clamp(-1.0, 1.0).acos()
+    };
+    let theta = angle(1.0, 0.0, (x1 - cx1) / rx, (y1 - cy1) / ry);
+    let mut delta = angle(
+        (x1 - cx1) / rx,
+        (y1 - cy1) / ry,
+        (-x1 - cx1) / rx,
+        (-y1 - cy1) / ry,
+    ) % (2.0 * f32::consts::PI);
+    if !sweep && delta > 0.0 {
+        delta -= 2.0 * f32::consts::PI;
+    } else if sweep && delta < 0.0 {
+        delta += 2.0 * f32::consts::PI;
+    }
+
+    let count = segment_count(delta.abs() / arc_step(rx.max(ry), tolerance), 1);
+    for i in 1..count {
+        let t = theta + delta * (i as f32 / count as f32);
+        let (sin_t, cos_t) = t.sin_cos();
+        points.push(Vector2 {
+            x: center.x + rx * cos_t * cos_phi - ry * sin_t * sin_phi,
+            y: center.y + rx * cos_t * sin_phi + ry * sin_t * cos_phi,
+        });
+    }
+    // land exactly on the endpoint so closing checks still match
+    points.push(to);
+}
+
+// Parses the d attribute of a path, curves are flattened so no point strays further than
+// tolerance from the real curve. Like browsers do, anything after a malformed command is dropped.
+pub fn parse_path(data: &str, tolerance: f32) -> Vec<Polyline> {
+    let mut polylines: Vec<Polyline> = vec![];
+    let mut current: Vec<Vector2> = vec![];
+    let mut lexer = PathLexer::new(data);
+
+    let mut position = Vector2::zero();
+    let mut subpath_start = Vector2::zero();
+    // reflected control points for S and T
+    let mut last_cubic_control: Option<Vector2> = None;
+    let mut last_quadratic_control: Option<Vector2> = None;
+    let mut command: Option<u8> = None;
+
+    loop {
+        let next = match lexer.next_command() {
+            Some(c) => c,
+            None => {
+                if !lexer.has_number() {
+                    break;
+                }
+                match command {
+                    // extra coordinate pairs after a moveto are treated as linetos
+                    Some(b'M') => b'L',
+                    Some(b'm') => b'l',
+                    Some(b'Z') | Some(b'z') | None => break,
+                    Some(c) => c,
+                }
+            }
+        };
+        command = Some(next);
+        let relative = next.is_ascii_lowercase();
+        let origin = if relative { position } else { Vector2::zero() };
+
+        let mut cubic_control: Option<Vector2> = None;
+        let mut quadratic_control: Option<Vector2> = None;
+        match next.to_ascii_uppercase() {
+            b'M' => {
+                let Some(point) = lexer.point() else { break };
+                if current.len() > 1 {
+                    polylines.push(Polyline {
+                        points: current,
+                        closed: false,
+                    });
+                }
+                position = origin + point;
+                subpath_start = position;
+                current = vec![position];
+            }
+            b'L' => {
+                let Some(point) = lexer.point() else { break };
+                position = origin + point;
+                current.push(position);
+            }
+            b'H' => {
+                let Some(x) = lexer.number() else { break };
+                position.x = if relative { position.x + x } else { x };
+                current.push(position);
+            }
+            b'V' => {
+                let Some(y) = lexer.number() else { break };
+                position.y = if relative { position.y + y } else { y };
+                current.push(position);
+            }
+            b'C' => {
+                let (Some(c1), Some(c2), Some(end)) = (lexer.point(), lexer.point(), lexer.point())
+                else {
+                    break;
+                };
+                flatten_cubic(
+                    &mut current,
+                    position,
+                    origin + c1,
+                    origin + c2,
+                    origin + end,
+                    tolerance,
+                );
+                cubic_control = Some(origin + c2);
+                position = origin + end;
+            }
+            b'S' => {
+                let (Some(c2), Some(end)) = (lexer.point(), lexer.point()) else {
+                    break;
+                };
+                let c1 = match last_cubic_control {
+                    Some(control) => position * 2.0 - control,
+                    None => position,
+                };
+                flatten_cubic(
+                    &mut current,
+                    position,
+                    c1,
+                    origin + c2,
+                    origin + end,
+                    tolerance,
+                );
+                cubic_control = Some(origin + c2);
+                position = origin + end;
+            }
+            b'Q' => {
+                let (Some(c1), Some(end)) = (lexer.point(), lexer.point()) else {
+                    break;
+                };
+                flatten_quadratic(&mut current, position, origin + c1, origin + end, tolerance);
+                quadratic_control = Some(origin + c1);
+                position = origin + end;
+            }
+            b'T' => {
+                let Some(end) = lexer.point() else { break };
+                let c1 = match last_quadratic_control {
+                    Some(control) => position * 2.0 - control,
+                    None => position,
+                };
+                flatten_quadratic(&mut current, position, c1, origin + end, tolerance);
+                quadratic_control = Some(c1);
+                position = origin + end;
+            }
+            b'A' => {
+                let (Some(rx), Some(ry), Some(rotation)) =
+                    (lexer.number(), lexer.number(), lexer.number())
+                else {
+                    break;
+                };
+                let (Some(large_arc), Some(sweep), Some(end)) =
+                    (lexer.flag(), lexer.flag(), lexer.point())
+                else {
+                    break;
+                };
+                flatten_arc(
+                    &mut current,
+                    position,
+                    Vector2 { x: rx, y: ry },
+                    rotation,
+                    (large_arc, sweep),
+                    origin + end,
+                    tolerance,
+                );
+                position = origin + end;
+            }
+            b'Z' => {
+                if current.len() > 1 {
+                    polylines.push(Polyline {
+                        points: current,
+                        closed: true,
+                    });
+                }
+                position = subpath_start;
+                current = vec![position];
+            }
+            _ => break,
+        }
+        last_cubic_control = cubic_control;
+        last_quadratic_control = quadratic_control;
+    }
+    if current.len() > 1 {
+        polylines.push(Polyline {
+            points: current,
+            closed: false,
+        });
+    }
+    polylines
+}
+
+// Points of a polyline or polygon element, an odd trailing coordinate is ignored
+pub fn parse_points(data: &str, closed: bool) -> Polyline {
+    let mut points: Vec<Vector2> = vec![];
+    let mut lexer = PathLexer::new(data);
+    while lexer.has_number() {
+        match lexer.point() {
+            Some(point) => points.push(point),
+            None => break,
+        }
+    }
+    Polyline { points, closed }
+}
+
+// https://www.w3.org/TR/SVG2/shapes.html#RectElement, corners go clockwise from the top left.
+// None when it has no width or height, those dont get drawn
+pub fn rect(
+    position: Vector2,
+    size: Vector2,
+    radius: (Option<f32>, Option<f32>),
+    tolerance: f32,
+) -> Option<Polyline> {
+    if !(size.x > 0.0 && size.y > 0.0) {
+        return None;
+    }
+    let (rx, ry) = match radius {
+        (None, None) => (0.0, 0.0),
+        (Some(rx), None) => (rx, rx),
+        (None, Some(ry)) => (ry, ry),
+        (Some(rx), Some(ry)) => (rx, ry),
+    };
+    let rx = rx.abs().min(size.x / 2.0);
+    let ry = ry.abs().min(size.y / 2.0);
+    let corner = Vector2 { x: rx, y: ry };
+
+    let x = position.x;
+    let y = position.y;
+    let right = x + size.x;
+    let bottom = y + size.y;
+    let mut points: Vec<Vector2> = vec![Vector2 { x: x + rx, y }];
+    if rx == 0.0 || ry == 0.0 {
+        points.push(Vector2 { x: right, y });
+        points.push(Vector2 {
+            x: right,
+            y: bottom,
+        });
+        points.push(Vector2 { x, y: bottom });
+        points.push(Vector2 { x, y });
+    } else {
+        let corners = [
+            (
+                Vector2 { x: right - rx, y },
+                Vector2 {
+                    x: right,
+                    y: y + ry,
+                },
+            ),
+            (
+                Vector2 {
+                    x: right,
+                    y: bottom - ry,
+                },
+                Vector2 {
+                    x: right - rx,
+                    y: bottom,
+                },
+            ),
+            (
+                Vector2 {
+                    x: x + rx,
+                    y: bottom,
+                },
+                Vector2 { x, y: bottom - ry },
+            ),
+            (Vector2 { x, y: y + ry }, Vector2 { x: x + rx, y }),
+        ];
+        for (from, to) in corners {
+            points.push(from);
+            flatten_arc(&mut points, from, corner, 0.0, (false, true), to, tolerance);
+        }
+    }
+    // the start point gets repeated by the last edge, closing handles that side
+    points.pop();
+    Some(Polyline {
+        points,
+        closed: true,
+    })
+}
+
+// Circles are just ellipses with rx == ry. None unless both radii are above 0, like rect
+pub fn ellipse(center: Vector2, radius: Vector2, tolerance: f32) -> Option<Polyline> {
+    let rx = radius.x;
+    let ry = radius.y;
+    if !(rx > 0.0 && ry > 0.0) {
+        return None;
+    }
+    let mut points: Vec<Vector2> = vec![];
+    let count = segment_count(2.0 * f32::consts::PI / arc_step(rx.max(ry), tolerance), 4);
+    for i in 0..count {
+        let (sin_t, cos_t) = (2.0 * f32::consts::PI * (i as f32 / count as f32)).sin_cos();
+        points.push(Vector2 {
+            x: center.x + rx * cos_t,
+            y: center.y + ry * sin_t,
+        });
+    }
+    Some(Polyline {
+        points,
+        closed: true,
+    })
+}
+
+// Affine matrix in the same a-f order svg uses, [a c e; b d f; 0 0 1]
+#[derive(Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub a: f32,
+    pub b: f32,
+    pub c: f32,
+    pub d: f32,
+    pub e: f32,
+    pub f: f32,
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Transform {
+            a: 1.0,
+            b: 0.0,
+            c: 0.0,
+            d: 1.0,
+            e: 0.0,
+            f: 0.0,
+        }
+    }
+    pub fn translate(x: f32, y: f32) -> Self {
+        Transform {
+            e: x,
+            f: y,
+            ..Self::identity()
+        }
+    }
+    pub fn scale(x: f32, y: f32) -> Self {
+        Transform {
+            a: x,
+            d: y,
+            ..Self::identity()
+        }
+    }
+    pub fn rotate(degrees: f32) -> Self {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        Transform {
+            a: cos,
+            b: sin,
+            c: -sin,
+            d: cos,
+            e: 0.0,
+            f: 0.0,
+        }
+    }
+    // other is applied to points first, then self
+    pub fn then(&self, other: &Transform) -> Self {
+        Transform {
+            a: self.a * other.a + self.c * other.b,
+            b: self.b * other.a + self.d * other.b,
+            c: self.a * other.c + self.c * other.d,
+            d: self.b * other.c + self.d * other.d,
+            e: self.a * other.e + self.c * other.f + self.e,
+            f: self.b * other.e + self.d * other.f + self.f,
+        }
+    }
+    pub fn apply(&self, point: Vector2) -> Vector2 {
+        Vector2 {
+            x: self.a * point.x + self.c * point.y + self.e,
+            y: self.b * point.x + self.d * point.y + self.f,
+        }
+    }
+    // how much lengths grow on average, used to keep curve tolerance in root units
+    pub fn scale_factor(&self) -> f32 {
+        (self.a * self.d - self.b * self.c).abs().sqrt()
+    }
+}
+
+// https://www.w3.org/TR/css-transforms-1/#svg-transform, unknown functions are skipped
+pub fn parse_transform(data: &str) -> Transform {
+    let mut transform = Transform::identity();
+    for function in data.split(')') {
+        let Some((name, args)) = function.split_once('(') else {
+            continue;
+        };
+        let mut lexer = PathLexer::new(args);
+        let mut values: Vec<f32> = vec![];
+        while lexer.has_number() {
+            match lexer.number() {
+                Some(value) => values.push(value),
+                None => break,
+            }
+        }
+        let name = name.trim_matches(|c: char| c.is_whitespace() || c == ',');
+        let next = match (name, values.as_slice()) {
+            ("matrix", [a, b, c, d, e, f]) => Transform {
+                a: *a,
+                b: *b,
+                c: *c,
+                d: *d,
+                e: *e,
+                f: *f,
+            },
+            ("translate", [x]) => Transform::translate(*x, 0.0),
+            ("translate", [x, y]) => Transform::translate(*x, *y),
+            ("scale", [x]) => Transform::scale(*x, *x),
+            ("scale", [x, y]) => Transform::scale(*x, *y),
+            ("rotate", [angle]) => Transform::rotate(*angle),
+            ("rotate", [angle, x, y]) => Transform::translate(*x, *y)
+                .then(&Transform::rotate(*angle))
+                .then(&Transform::translate(-*x, -*y)),
+            ("skewX", [angle]) => Transform {
+                c: angle.to_radians().tan(),
+                ..Transform::identity()
+            },
+            ("skewY", [angle]) => Transform {
+                b: angle.to_radians().tan(),
+                ..Transform::identity()
+            },
+            _ => continue,
+        };
+        transform = transform.then(&next);
+    }
+    transform
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Unit {
+    Millimeter,
+    Centimeter,
+    Inch,
+    Point,
+    Pica,
+    Pixel,
+}
+
+impl Unit {
+    pub fn centimeters(&self) -> f32 {
+        match self {
+            Unit::Millimeter => 0.1,
+            Unit::Centimeter => 1.0,
+            Unit::Inch => 2.54,
+            Unit::Point => 2.54 / 72.0,
+            Unit::Pica => 2.54 / 6.0,
+            Unit::Pixel => 2.54 / 96.0,
+        }
+    }
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            Unit::Millimeter => "mm",
+            Unit::Centimeter => "cm",
+            Unit::Inch => "in",
+            Unit::Point => "pt",
+            Unit::Pica => "pc",
+            Unit::Pixel => "px",
+        }
+    }
+}
+
+// Lengths like width="145in", unitless numbers are pixels. Percentages have nothing to be
+// relative to here so they are rejected.
+pub fn parse_length(data: &str) -> Option<(f32, Unit)> {
+    let data = data.trim();
+    let number = data.trim_end_matches(|c: char| c.is_ascii_alphabetic() || c == '%');
+    let suffix = &data[number.len()..];
+    let unit = match suffix {
+        "mm" => Unit::Millimeter,
+        "cm" => Unit::Centimeter,
+        "in" => Unit::Inch,
+        "pt" => Unit::Point,
+        "pc" => Unit::Pica,
+        "" | "px" => Unit::Pixel,
+        _ => return None,
+    };
+    Some((number.trim().parse::<f32>().ok()?, unit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Vector2, b: Vector2) -> bool {
+        (a - b).length() < 1e-4
+    }
+
+    fn points(polyline: &Polyline) -> Vec<(f32, f32)> {
+        polyline.points.iter().map(|p| (p.x, p.y)).collect()
+    }
+
+    #[test]
+    fn absolute_and_relative_lines() {
+        let expected = vec![(10.0, 20.0), (30.0, 40.0), (50.0, 40.0), (50.0, 60.0)];
+        let absolute = parse_path("M 10 20 L 30 40 H 50 V 60 Z", 0.1);
+        assert_eq!(absolute.len(), 1);
+        assert!(absolute[0].closed);
+        assert_eq!(points(&absolute[0]), expected);
+
+        let relative = parse_path("m10,20 l20,20 h20 v20 z", 0.1);
+        assert_eq!(relative.len(), 1);
+        assert!(relative[0].closed);
+        assert_eq!(points(&relative[0]), expected);
+    }
+
+    #[test]
+    fn close_goes_back_to_the_start() {
+        let polylines = parse_path("M0 0 h10 v10 z m5 5 h1", 0.1);
+        assert_eq!(polylines.len(), 2);
+        assert_eq!(points(&polylines[1]), vec![(5.0, 5.0), (6.0, 5.0)]);
+    }
+
+    #[test]
+    fn repeated_commands_without_letters() {
+        // pairs after a moveto are linetos, other commands just repeat
+        let moves = parse_path("M0 0 10 0 10 10", 0.1);
+        assert_eq!(
+            points(&moves[0]),
+            vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0)]
+        );
+        let relative = parse_path("m1 1 10 0 0 10", 0.1);
+        assert_eq!(
+            points(&relative[0]),
+            vec![(1.0, 1.0), (11.0, 1.0), (11.0, 11.0)]
+        );
+        let horizontal = parse_path("M0 0 h1 2 3", 0.1);
+        assert_eq!(
+            points(&horizontal[0]),
+            vec![(0.0, 0.0), (1.0, 0.0), (3.0, 0.0), (6.0, 0.0)]
+        );
+    }
+
+    #[test]
+    fn squashed_numbers() {
+        let exponent = parse_path("M1e-3 2E2L-1.5e+1-.5", 0.1);
+        assert_eq!(points(&exponent[0]), vec![(0.001, 200.0), (-15.0, -0.5)]);
+        // a second dot starts the next number
+        let dots = parse_path("M.5.5L1-1", 0.1);
+        assert_eq!(points(&dots[0]), vec![(0.5, 0.5), (1.0, -1.0)]);
+    }
+
+    #[test]
+    fn arc_flags_without_separators() {
+        // large arc 0, sweep 1, then the relative end 10,0
+        let polylines = parse_path("M0 0a5 5 0 0110 0", 0.01);
+        assert_eq!(polylines.len(), 1);
+        let arc = &polylines[0].points;
+        assert!(close(*arc.last().unwrap(), Vector2::new(10.0, 0.0)));
+        let center = Vector2::new(5.0, 0.0);
+        for point in arc {
+            assert!(((*point - center).length() - 5.0).abs() < 1e-3);
+        }
+        // sweeping the positive way from the left goes over the top
+        let top = arc
+            .iter()
+            .map(|point| point.y)
+            .fold(f32::INFINITY, f32::min);
+        assert!((top + 5.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn malformed_commands_drop_the_rest() {
+        let polylines = parse_path("M0 0 L10 0 L20", 0.1);
+        assert_eq!(points(&polylines[0]), vec![(0.0, 0.0), (10.0, 0.0)]);
+    }
+
+    #[test]
+    fn transforms() {
+        let point = Vector2::new(1.0, 1.0);
+        let matrix = parse_transform("matrix(1 2 3 4 5 6)");
+        assert!(close(matrix.apply(point), Vector2::new(9.0, 12.0)));
+        let translate = parse_transform("translate(10)");
+        assert!(close(translate.apply(point), Vector2::new(11.0, 1.0)));
+        let translate = parse_transform("translate(10, 20)");
+        assert!(close(translate.apply(point), Vector2::new(11.0, 21.0)));
+        let rotate = parse_transform("rotate(90 10 10)");
+        assert!(close(
+            rotate.apply(Vector2::new(20.0, 10.0)),
+            Vector2::new(10.0, 20.0)
+        ));
+        // the rightmost function is applied first
+        let both = parse_transform("translate(10 0) scale(2)");
+        assert!(close(both.apply(point), Vector2::new(12.0, 2.0)));
+        let unknown = parse_transform("wobble(3) translate(1 1)");
+        assert!(close(unknown.apply(point), Vector2::new(2.0, 2.0)));
+    }
+
+    #[test]
+    fn lengths() {
+        assert_eq!(parse_length("210mm"), Some((210.0, Unit::Millimeter)));
+        assert_eq!(parse_length(" 29.7cm "), Some((29.7, Unit::Centimeter)));
+        assert_eq!(parse_length("8.5in"), Some((8.5, Unit::Inch)));
+        assert_eq!(parse_length("12pt"), Some((12.0, Unit::Point)));
+        assert_eq!(parse_length("3pc"), Some((3.0, Unit::Pica)));
+        assert_eq!(parse_length("640px"), Some((640.0, Unit::Pixel)));
+        assert_eq!(parse_length("640"), Some((640.0, Unit::Pixel)));
+        assert_eq!(parse_length("50%"), None);
+        assert_eq!(parse_length("12furlongs"), None);
+        assert_eq!(parse_length("mm"), None);
+    }
+
+    #[test]
+    fn broken_tolerance_stays_bounded() {
+        for tolerance in [0.0, -1.0, f32::NAN] {
+            let curves = parse_path(
+                "M0 0 C10 20 30 20 40 0 Q50 -20 60 0 A10 10 0 0 1 80 0",
+                tolerance,
+            );
+            let points: usize = curves.iter().map(|polyline| polyline.points.len()).sum();
+            assert!(
+                points <= 3 * MAX_SEGMENTS + 1,
+                "{} gave {}",
+                tolerance,
+                points
+            );
+            let circle = ellipse(Vector2::new(0.0, 0.0), Vector2::new(5.0, 5.0), tolerance);
+            assert!((4..=MAX_SEGMENTS).contains(&circle.unwrap().points.len()));
+            let rounded = rect(
+                Vector2::new(0.0, 0.0),
+                Vector2::new(10.0, 10.0),
+                (Some(2.0), None),
+                tolerance,
+            );
+            assert!(rounded.unwrap().points.len() <= 4 * (MAX_SEGMENTS + 1));
+        }
+    }
+
+    #[test]
+    fn rounded_rect_corners() {
+        let origin = Vector2::new(0.0, 0.0);
+        let size = Vector2::new(10.0, 10.0);
+        let rounded = rect(origin, size, (Some(2.0), Some(2.0)), 0.01).unwrap();
+        assert!(rounded.closed);
+        assert!(close(rounded.points[0], Vector2::new(2.0, 0.0)));
+        // each corner is a quarter circle around a point inset by the radius
+        for point in &rounded.points {
+            assert!(point.x >= -1e-4 && point.x <= 10.0 + 1e-4);
+            assert!(point.y >= -1e-4 && point.y <= 10.0 + 1e-4);
+            if (point.x < 2.0 || point.x > 8.0) && (point.y < 2.0 || point.y > 8.0) {
+                let center = Vector2::new(point.x.clamp(2.0, 8.0), point.y.clamp(2.0, 8.0));
+                let distance = (*point - center).length();
+                assert!((distance - 2.0).abs() < 1e-3, "{:?}", point);
+            }
+        }
+        for corner in [(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)] {
+            assert!(!points(&rounded).contains(&corner));
+        }
+
+        let square = rect(origin, size, (None, None), 0.01).unwrap();
+        assert_eq!(
+            points(&square),
+            vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)]
+        );
+    }
+
+    #[test]
+    fn rect_radius_fills_in_the_other() {
+        let origin = Vector2::new(0.0, 0.0);
+        let size = Vector2::new(10.0, 10.0);
+        let both = rect(origin, size, (Some(3.0), Some(3.0)), 0.1).unwrap();
+        let only_x = rect(origin, size, (Some(3.0), None), 0.1).unwrap();
+        let only_y = rect(origin, size, (None, Some(3.0)), 0.1).unwrap();
+        assert_eq!(points(&only_x), points(&both));
+        assert_eq!(points(&only_y), points(&both));
+    }
+
+    #[test]
+    fn rect_radius_clamps_to_half_the_size() {
+        let origin = Vector2::new(0.0, 0.0);
+        let size = Vector2::new(10.0, 6.0);
+        let huge = rect(origin, size, (Some(20.0), Some(20.0)), 0.1).unwrap();
+        let half = rect(origin, size, (Some(5.0), Some(3.0)), 0.1).unwrap();
+        assert_eq!(points(&huge), points(&half));
+        // the top edge shrinks down to a single point in the middle
+        assert!(close(huge.points[0], Vector2::new(5.0, 0.0)));
+        let left = huge
+            .points
+            .iter()
+            .map(|point| point.x)
+            .fold(f32::INFINITY, f32::min);
+        assert!(left.abs() < 1e-4);
+    }
+
+    #[test]
+    fn empty_shapes_dont_render() {
+        let origin = Vector2::new(0.0, 0.0);
+        for size in [(0.0, 10.0), (10.0, 0.0), (-10.0, 10.0), (10.0, -5.0)] {
+            let size = Vector2::new(size.0, size.1);
+            assert!(rect(origin, size, (None, None), 0.1).is_none());
+            assert!(rect(origin, size, (Some(1.0), None), 0.1).is_none());
+        }
+        for radius in [
+            (0.0, 0.0),
+            (5.0, 0.0),
+            (0.0, 5.0),
+            (-5.0, -5.0),
+            (5.0, -5.0),
+        ] {
+            let radius = Vector2::new(radius.0, radius.1);
+            assert!(ellipse(origin, radius, 0.1).is_none());
+        }
+        assert!(ellipse(origin, Vector2::new(5.0, 2.0), 0.1).is_some());
+    }
+
+    #[test]
+    fn polygons_close_and_polylines_dont() {
+        let polygon = parse_points("0,0 10,0 10,10", true);
+        let polyline = parse_points("0,0 10,0 10,10", false);
+        assert!(polygon.closed);
+        assert!(!polyline.closed);
+        assert_eq!(points(&polygon), points(&polyline));
+        assert_eq!(
+            points(&polygon),
+            vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0)]
+        );
+    }
+
+    #[test]
+    fn odd_trailing_coordinate_is_dropped() {
+        let polyline = parse_points("0 0, 10 0, 10 10, 5", false);
+        assert_eq!(
+            points(&polyline),
+            vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0)]
+        );
+    }
+}